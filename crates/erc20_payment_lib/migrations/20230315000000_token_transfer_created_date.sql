@@ -0,0 +1,3 @@
+ALTER TABLE "token_transfer" ADD COLUMN created_date DATETIME NULL;
+
+CREATE INDEX "idx_token_transfer_created_date" ON "token_transfer" (created_date);
@@ -0,0 +1,2 @@
+ALTER TABLE "token_transfer" ADD COLUMN execute_after DATETIME NULL;
+ALTER TABLE "token_transfer" ADD COLUMN deadline DATETIME NULL;
@@ -0,0 +1,18 @@
+CREATE TABLE "recurring_payment"
+(
+    id                  INTEGER     NOT NULL     PRIMARY KEY AUTOINCREMENT,
+    from_addr           TEXT        NOT NULL,
+    receiver_addr       TEXT        NOT NULL,
+    chain_id            INTEGER     NOT NULL,
+    token_addr          TEXT        NULL,
+    token_amount        TEXT        NOT NULL,
+    schedule            TEXT        NULL,
+    interval_secs       INTEGER     NULL,
+    start_date          DATETIME    NOT NULL,
+    end_date            DATETIME    NULL,
+    last_occurrence     DATETIME    NULL,
+    active              INTEGER     NOT NULL,
+    created_date        DATETIME    NOT NULL
+);
+
+CREATE INDEX "idx_token_transfer_payment_id" ON "token_transfer" (payment_id);
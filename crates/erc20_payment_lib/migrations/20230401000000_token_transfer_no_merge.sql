@@ -0,0 +1 @@
+ALTER TABLE "token_transfer" ADD COLUMN no_merge INTEGER NOT NULL DEFAULT 0;
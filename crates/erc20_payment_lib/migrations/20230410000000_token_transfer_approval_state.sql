@@ -0,0 +1 @@
+ALTER TABLE "token_transfer" ADD COLUMN approval_state TEXT NULL;
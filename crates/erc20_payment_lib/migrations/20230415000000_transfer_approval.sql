@@ -0,0 +1,11 @@
+CREATE TABLE "transfer_approval"
+(
+    id                  INTEGER     NOT NULL     PRIMARY KEY AUTOINCREMENT,
+    transfer_id         INTEGER     NOT NULL,
+    approver            TEXT        NOT NULL,
+    decision            TEXT        NOT NULL,
+    reason              TEXT        NULL,
+    created_date        DATETIME    NOT NULL,
+    CONSTRAINT "fk_transfer_approval_token_transfer" FOREIGN KEY ("transfer_id") REFERENCES "token_transfer" ("id"),
+    CONSTRAINT "uc_transfer_approval_approver" UNIQUE ("transfer_id", "approver")
+);
@@ -0,0 +1,8 @@
+CREATE TABLE "screening_address"
+(
+    id                  INTEGER     NOT NULL     PRIMARY KEY AUTOINCREMENT,
+    address             TEXT        NOT NULL,
+    note                TEXT        NULL,
+    created_date        DATETIME    NOT NULL,
+    CONSTRAINT "uc_screening_address" UNIQUE ("address")
+);
@@ -0,0 +1,5 @@
+ALTER TABLE "token_transfer" ADD COLUMN retry_of INTEGER NULL;
+ALTER TABLE "token_transfer" ADD COLUMN attempt INTEGER NOT NULL DEFAULT 1;
+ALTER TABLE "token_transfer" ADD COLUMN dead_letter INTEGER NOT NULL DEFAULT 0;
+
+CREATE INDEX "idx_token_transfer_retry_of" ON "token_transfer" (retry_of);
@@ -0,0 +1,14 @@
+CREATE TABLE "operator_action"
+(
+    id                  INTEGER     NOT NULL     PRIMARY KEY AUTOINCREMENT,
+    tx_id               INTEGER     NOT NULL,
+    action              TEXT        NOT NULL,
+    actor               TEXT        NOT NULL,
+    reason              TEXT        NOT NULL,
+    created_date        DATETIME    NOT NULL,
+    processed_date      DATETIME    NULL,
+    result              TEXT        NULL,
+    CONSTRAINT "fk_operator_action_tx" FOREIGN KEY ("tx_id") REFERENCES "tx" ("id")
+);
+
+CREATE INDEX "idx_operator_action_tx_id" ON "operator_action" (tx_id);
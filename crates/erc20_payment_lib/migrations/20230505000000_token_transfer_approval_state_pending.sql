@@ -0,0 +1 @@
+UPDATE "token_transfer" SET approval_state = 'pending_approval' WHERE approval_state = 'required';
@@ -0,0 +1 @@
+ALTER TABLE "token_transfer" ADD COLUMN waiting_for_funds INTEGER NOT NULL DEFAULT 0;
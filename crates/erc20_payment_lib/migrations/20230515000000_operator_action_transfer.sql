@@ -0,0 +1,24 @@
+CREATE TABLE "operator_action_new"
+(
+    id                  INTEGER     NOT NULL     PRIMARY KEY AUTOINCREMENT,
+    tx_id               INTEGER     NULL,
+    transfer_id         INTEGER     NULL,
+    action              TEXT        NOT NULL,
+    actor               TEXT        NOT NULL,
+    reason              TEXT        NOT NULL,
+    created_date        DATETIME    NOT NULL,
+    processed_date      DATETIME    NULL,
+    result              TEXT        NULL,
+    CONSTRAINT "fk_operator_action_tx" FOREIGN KEY ("tx_id") REFERENCES "tx" ("id"),
+    CONSTRAINT "fk_operator_action_transfer" FOREIGN KEY ("transfer_id") REFERENCES "token_transfer" ("id")
+);
+
+INSERT INTO "operator_action_new" (id, tx_id, action, actor, reason, created_date, processed_date, result)
+SELECT id, tx_id, action, actor, reason, created_date, processed_date, result FROM "operator_action";
+
+DROP TABLE "operator_action";
+
+ALTER TABLE "operator_action_new" RENAME TO "operator_action";
+
+CREATE INDEX "idx_operator_action_tx_id" ON "operator_action" (tx_id);
+CREATE INDEX "idx_operator_action_transfer_id" ON "operator_action" (transfer_id);
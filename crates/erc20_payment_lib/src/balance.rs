@@ -0,0 +1,295 @@
+use crate::db::ops::{get_average_gas_limit, get_queued_token_amounts, get_reserved_token_amounts};
+use crate::error::{CustomError, ErrorBag, PaymentError};
+use crate::eth::{get_balance, get_eth_addr_from_secret};
+use crate::setup::{ChainSetup, PaymentSetup};
+use crate::utils::u256_to_rust_dec;
+use crate::{err_custom_create, err_from};
+
+use serde::Serialize;
+use sqlx::SqlitePool;
+use web3::types::{Address, U256};
+
+/// Number of last confirmed transactions used to estimate gas of the next batch
+const GAS_ESTIMATION_TX_COUNT: i64 = 20;
+
+#[derive(Serialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct TokenBalance {
+    pub token_addr: Option<Address>,
+    pub symbol: String,
+    pub decimals: u32,
+    /// None when balance could not be read from chain
+    pub balance: Option<U256>,
+    /// Assigned to transactions which are not finished yet
+    pub reserved: U256,
+    /// Waiting in queue, not assigned to any transaction yet
+    pub queued: U256,
+}
+
+#[derive(Serialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct AccountBalance {
+    pub account: Address,
+    pub chain_id: i64,
+    pub chain_name: String,
+    pub native: TokenBalance,
+    pub token: Option<TokenBalance>,
+    /// Average gas limit of last confirmed transactions or gas left warning limit when there are none
+    pub gas_per_batch: u64,
+    /// Fee of single batch at max fee per gas
+    pub batch_fee: U256,
+    /// Batches the gas balance left after outgoing native transfers can cover
+    pub batches_left: Option<u64>,
+    pub error: Option<String>,
+}
+
+/// Token balance with outgoing amounts read from db, balance itself is read later from chain
+async fn get_token_outgoing(
+    conn: &SqlitePool,
+    chain_setup: &ChainSetup,
+    account: Address,
+    token_addr: Option<Address>,
+    symbol: &str,
+) -> Result<TokenBalance, PaymentError> {
+    let from_addr = format!("{account:#x}");
+    let token_addr_str = token_addr.map(|token_addr| format!("{token_addr:#x}"));
+    let decimals = chain_setup.get_decimals(token_addr).ok_or_else(|| {
+        err_custom_create!("Decimals of token {:?} not found in config", token_addr)
+    })?;
+
+    let mut reserved = U256::zero();
+    for amount in get_reserved_token_amounts(
+        conn,
+        &from_addr,
+        chain_setup.chain_id,
+        token_addr_str.as_deref(),
+    )
+    .await
+    .map_err(err_from!())?
+    {
+        reserved += U256::from_dec_str(&amount).map_err(err_from!())?;
+    }
+    let mut queued = U256::zero();
+    for amount in get_queued_token_amounts(
+        conn,
+        &from_addr,
+        chain_setup.chain_id,
+        token_addr_str.as_deref(),
+    )
+    .await
+    .map_err(err_from!())?
+    {
+        queued += U256::from_dec_str(&amount).map_err(err_from!())?;
+    }
+    Ok(TokenBalance {
+        token_addr,
+        symbol: symbol.to_string(),
+        decimals,
+        balance: None,
+        reserved,
+        queued,
+    })
+}
+
+/// Outgoing amounts and gas estimates of every sender on every configured chain,
+/// only db is used, so the connection is not held during RPC calls made by read_chain_balances
+pub async fn get_outgoing_amounts(
+    conn: &SqlitePool,
+    payment_setup: &PaymentSetup,
+) -> Result<Vec<AccountBalance>, PaymentError> {
+    let accounts = payment_setup
+        .secret_keys
+        .iter()
+        .map(get_eth_addr_from_secret)
+        .collect::<Vec<_>>();
+
+    let mut balances = Vec::new();
+    for chain_setup in payment_setup.chain_setup.values() {
+        for account in &accounts {
+            let native = get_token_outgoing(
+                conn,
+                chain_setup,
+                *account,
+                None,
+                &chain_setup.currency_gas_symbol,
+            )
+            .await?;
+            let token = match chain_setup.glm_address {
+                Some(glm_address) => Some(
+                    get_token_outgoing(
+                        conn,
+                        chain_setup,
+                        *account,
+                        Some(glm_address),
+                        &chain_setup.currency_glm_symbol,
+                    )
+                    .await?,
+                ),
+                None => None,
+            };
+
+            let gas_per_batch = get_average_gas_limit(
+                conn,
+                &format!("{account:#x}"),
+                chain_setup.chain_id,
+                GAS_ESTIMATION_TX_COUNT,
+            )
+            .await
+            .map_err(err_from!())?
+            .map(|gas_limit| gas_limit as u64)
+            .unwrap_or(chain_setup.gas_left_warning_limit);
+            balances.push(AccountBalance {
+                account: *account,
+                chain_id: chain_setup.chain_id,
+                chain_name: chain_setup.chain_name.clone(),
+                native,
+                token,
+                gas_per_batch,
+                batch_fee: chain_setup.max_fee_per_gas * U256::from(gas_per_batch),
+                batches_left: None,
+                error: None,
+            });
+        }
+    }
+    Ok(balances)
+}
+
+async fn read_token_balance(
+    payment_setup: &PaymentSetup,
+    chain_id: i64,
+    account: Address,
+    token_balance: &mut TokenBalance,
+) -> Option<String> {
+    let balance = match payment_setup.get_provider(chain_id) {
+        Ok(web3) => get_balance(web3, token_balance.token_addr, account).await,
+        Err(err) => Err(err),
+    };
+    match balance {
+        Ok(balance) => {
+            token_balance.balance = Some(balance);
+            None
+        }
+        Err(err) => {
+            log::warn!(
+                "Failed to get {} balance of {:#x} on chain {}: {}",
+                token_balance.symbol,
+                account,
+                chain_id,
+                err
+            );
+            Some(format!("{} balance: {}", token_balance.symbol, err))
+        }
+    }
+}
+
+/// Fill balances read from chain into entries returned by get_outgoing_amounts,
+/// errors of single RPC calls are reported in the entry instead of failing whole overview
+pub async fn read_chain_balances(payment_setup: &PaymentSetup, balances: &mut [AccountBalance]) {
+    for balance in balances.iter_mut() {
+        let native_error = read_token_balance(
+            payment_setup,
+            balance.chain_id,
+            balance.account,
+            &mut balance.native,
+        )
+        .await;
+        let token_error = match balance.token.as_mut() {
+            Some(token) => {
+                read_token_balance(payment_setup, balance.chain_id, balance.account, token).await
+            }
+            None => None,
+        };
+
+        let batch_fee = balance.batch_fee;
+        let native = &balance.native;
+        balance.batches_left = native.balance.map(|native_balance| {
+            let available = native_balance
+                .saturating_sub(native.reserved)
+                .saturating_sub(native.queued);
+            if batch_fee.is_zero() {
+                u64::MAX
+            } else {
+                std::cmp::min(available / batch_fee, U256::from(u64::MAX)).as_u64()
+            }
+        });
+
+        let errors = [native_error, token_error]
+            .into_iter()
+            .flatten()
+            .collect::<Vec<_>>();
+        balance.error = if errors.is_empty() {
+            None
+        } else {
+            Some(errors.join(", "))
+        };
+    }
+}
+
+/// Native and token balances of every sender on every configured chain
+pub async fn get_account_balances(
+    conn: &SqlitePool,
+    payment_setup: &PaymentSetup,
+) -> Result<Vec<AccountBalance>, PaymentError> {
+    let mut balances = get_outgoing_amounts(conn, payment_setup).await?;
+    read_chain_balances(payment_setup, &mut balances).await;
+    Ok(balances)
+}
+
+/// Human readable table of the account balances
+pub fn format_account_balances(balances: &[AccountBalance]) -> Result<String, PaymentError> {
+    let amount_to_string = |amount: Option<U256>, decimals: u32| -> Result<String, PaymentError> {
+        match amount {
+            Some(amount) => Ok(u256_to_rust_dec(amount, Some(decimals))
+                .map_err(err_from!())?
+                .to_string()),
+            None => Ok("-".to_string()),
+        }
+    };
+    let mut out = format!(
+        "{:<12} {:<42} {:>24} {:>22} {:>24} {:>22} {:>12}\n",
+        "chain",
+        "account",
+        "gas balance",
+        "gas outgoing",
+        "token balance",
+        "token outgoing",
+        "batches left"
+    );
+    for balance in balances {
+        let with_symbol = |amount: String, symbol: &str| format!("{amount} {symbol}");
+        out += &format!(
+            "{:<12} {:<42} {:>24} {:>22} {:>24} {:>22} {:>12}\n",
+            balance.chain_name,
+            format!("{:#x}", balance.account),
+            with_symbol(
+                amount_to_string(balance.native.balance, balance.native.decimals)?,
+                &balance.native.symbol
+            ),
+            amount_to_string(
+                Some(balance.native.reserved + balance.native.queued),
+                balance.native.decimals
+            )?,
+            match &balance.token {
+                Some(token) => with_symbol(
+                    amount_to_string(token.balance, token.decimals)?,
+                    &token.symbol
+                ),
+                None => "-".to_string(),
+            },
+            match &balance.token {
+                Some(token) => {
+                    amount_to_string(Some(token.reserved + token.queued), token.decimals)?
+                }
+                None => "-".to_string(),
+            },
+            balance
+                .batches_left
+                .map(|b| b.to_string())
+                .unwrap_or_else(|| "-".to_string()),
+        );
+        if let Some(error) = &balance.error {
+            out += &format!("  error: {error}\n");
+        }
+    }
+    Ok(out)
+}
@@ -1,4 +1,5 @@
-use serde::Deserialize;
+use rust_decimal::Decimal;
+use serde::{Deserialize, Serialize};
 use std::collections::btree_map::BTreeMap as Map;
 
 use std::fs;
@@ -15,6 +16,8 @@ pub struct AdditionalOptions {
     pub generate_tx_only: bool,
     ///Skip multi contract check when generating txs
     pub skip_multi_contract_check: bool,
+    ///Ignore payout thresholds and batching policy on first gathering
+    pub force_flush: bool,
 }
 
 impl Default for AdditionalOptions {
@@ -23,6 +26,7 @@ impl Default for AdditionalOptions {
             keep_running: true,
             generate_tx_only: false,
             skip_multi_contract_check: false,
+            force_flush: false,
         }
     }
 }
@@ -33,12 +37,65 @@ pub struct Engine {
     pub service_sleep: u64,
     pub process_sleep: u64,
     pub automatic_recover: bool,
+    /// Interval in seconds between gathering pending transfers into transactions
+    #[serde(default = "default_gather_interval")]
+    pub gather_interval: u64,
+    /// Failed transfers are not retried when not set
+    pub retry: Option<RetrySettings>,
+}
+
+#[derive(Deserialize, Debug, Clone)]
+#[serde(rename_all = "kebab-case")]
+pub struct RetrySettings {
+    /// Number of attempts (including the first one) before transfer is moved to dead letter
+    pub max_attempts: u32,
+    /// Delay in seconds before the first retry
+    #[serde(default = "default_retry_backoff")]
+    pub backoff: u64,
+    /// Delay is multiplied by this factor for every next retry
+    #[serde(default = "default_retry_backoff_multiplier")]
+    pub backoff_multiplier: f64,
+}
+
+fn default_retry_backoff() -> u64 {
+    60
+}
+
+fn default_retry_backoff_multiplier() -> f64 {
+    2.0
+}
+
+fn default_gather_interval() -> u64 {
+    20
 }
 
 #[derive(Deserialize, Debug, Clone)]
 pub struct Config {
     pub chain: Map<String, Chain>,
     pub engine: Engine,
+    pub screening: Option<ScreeningSettings>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+pub enum ScreeningMode {
+    /// Only listed receivers (and receiver accounts from ETH_RECEIVERS) are paid
+    Allowlist,
+    /// Listed receivers are never paid
+    Denylist,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(rename_all = "kebab-case")]
+pub struct ScreeningSettings {
+    pub mode: ScreeningMode,
+    #[serde(default)]
+    pub addresses: Vec<Address>,
+    /// File with one address per line (first column of CSV, lines starting with # are skipped)
+    pub file: Option<String>,
+    /// Include addresses from screening_address table
+    #[serde(default)]
+    pub database: bool,
 }
 
 #[derive(Deserialize, Debug, Clone)]
@@ -46,6 +103,101 @@ pub struct Config {
 pub struct MultiContractSettings {
     pub address: Address,
     pub max_at_once: usize,
+    /// Use golemTransferDirectPacked, allowance check is skipped in this mode
+    #[serde(default)]
+    pub direct: bool,
+    /// Gas limit of single batch, number of receivers is derived from gas estimated for previous batches
+    pub max_gas: Option<u64>,
+}
+
+#[derive(Deserialize, Debug, Clone)]
+#[serde(rename_all = "kebab-case")]
+pub struct NativeMultiContractSettings {
+    /// Payable contract exposing disperseEther(address[],uint256[])
+    pub address: Address,
+    pub max_at_once: usize,
+}
+
+#[derive(Deserialize, Debug, Clone)]
+#[serde(rename_all = "kebab-case")]
+pub struct MetaTransactionSettings {
+    /// Account submitting executeMetaTransaction and paying for gas (its key has to be loaded)
+    pub relayer: Address,
+}
+
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+pub enum AllowancePolicyKind {
+    /// Approve maximum possible amount once
+    Unlimited,
+    /// Approve exactly the amount needed by the batch
+    ExactPerBatch,
+    /// Approve fixed amount (cap), batches exceeding it are rejected
+    Cap,
+}
+
+#[derive(Deserialize, Debug, Clone)]
+#[serde(rename_all = "kebab-case")]
+pub struct AllowanceSettings {
+    pub policy: AllowancePolicyKind,
+    /// Allowance cap in token units, required for cap policy
+    pub cap: Option<Decimal>,
+    /// Verify cached unlimited allowance on chain again after given number of seconds,
+    /// one hour when not set
+    pub recheck_interval: Option<u64>,
+}
+
+#[derive(Deserialize, Debug, Clone)]
+#[serde(rename_all = "kebab-case")]
+pub struct BatchingSettings {
+    /// Minimum number of distinct receivers before the batch is sent
+    pub min_batch_size: Option<usize>,
+    /// Maximum time in seconds the oldest transfer waits for the batch to fill up
+    pub max_wait_time: Option<u64>,
+    /// Send batches right away when gas price (in Gwei) is below this threshold
+    pub flush_gas_price: Option<f64>,
+}
+
+#[derive(Deserialize, Debug, Clone)]
+#[serde(rename_all = "kebab-case")]
+pub struct SpendingLimits {
+    /// Maximum amount of single transfer
+    pub max_per_transfer: Option<Decimal>,
+    /// Maximum amount sent to one receiver in single transaction,
+    /// breaching transfers require approval
+    pub max_per_receiver_per_transaction: Option<Decimal>,
+    /// Maximum total amount of single batch transaction,
+    /// bigger batches are split into more transactions
+    pub max_per_batch: Option<Decimal>,
+    /// Maximum amount sent to one receiver during last 24 hours
+    pub max_per_receiver_daily: Option<Decimal>,
+    /// Maximum amount sent by one sender during last 24 hours
+    pub max_per_sender_daily: Option<Decimal>,
+}
+
+#[derive(Deserialize, Debug, Clone)]
+#[serde(rename_all = "kebab-case")]
+pub struct ApprovalSettings {
+    /// Transfers above this amount (in ether/token units) require approval,
+    /// transfers of tokens not found in config require approval when it is set
+    pub amount_above: Option<Decimal>,
+    /// First transfer from sender to the receiver requires approval
+    #[serde(default)]
+    pub new_receiver: bool,
+    /// All transfers of these tokens require approval
+    #[serde(default)]
+    pub tokens: Vec<Address>,
+    /// Number of distinct approvers needed to release the transfer
+    #[serde(default = "default_required_approvers")]
+    pub required_approvers: usize,
+    /// Operators allowed to approve or reject transfers, each one has to present
+    /// token set in APPROVER_TOKEN_<NAME> env variable
+    #[serde(default)]
+    pub approvers: Vec<String>,
+}
+
+fn default_required_approvers() -> usize {
+    1
 }
 
 #[derive(Deserialize, Debug, Clone)]
@@ -60,6 +212,20 @@ pub struct Chain {
     pub gas_left_warning_limit: u64,
     pub token: Option<Token>,
     pub multi_contract: Option<MultiContractSettings>,
+    pub native_multi_contract: Option<NativeMultiContractSettings>,
+    pub meta_transaction: Option<MetaTransactionSettings>,
+    pub allowance: Option<AllowanceSettings>,
+    /// Batching policy of native currency and tokens without own policy
+    pub batching: Option<BatchingSettings>,
+    /// Spending limits for native currency (in ether units)
+    pub native_limits: Option<SpendingLimits>,
+    /// Native transfers to receiver are kept queued until their sum reaches this amount (in ether units)
+    pub native_min_payout: Option<Decimal>,
+    /// Rules holding transfers until approved by operators
+    pub approval: Option<ApprovalSettings>,
+    /// Keep every transfer as separate on-chain entry instead of summing per receiver
+    #[serde(default)]
+    pub no_merge: bool,
     pub transaction_timeout: u64,
     pub confirmation_blocks: u64,
     pub faucet_eth_amount: Option<f64>,
@@ -68,10 +234,19 @@ pub struct Chain {
 }
 
 #[derive(Deserialize, Debug, Clone)]
+#[serde(rename_all = "kebab-case")]
 pub struct Token {
     pub symbol: String,
     pub address: Address,
     pub faucet: Option<Address>,
+    /// Number of token decimals, 18 when not set
+    pub decimals: Option<u32>,
+    /// Transfers to receiver are kept queued until their sum reaches this amount (in tokens)
+    pub min_payout: Option<Decimal>,
+    /// Transfers breaching the limits require approval (in tokens)
+    pub limits: Option<SpendingLimits>,
+    /// Overrides chain batching policy for this token
+    pub batching: Option<BatchingSettings>,
 }
 
 impl Config {
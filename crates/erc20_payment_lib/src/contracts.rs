@@ -7,8 +7,9 @@ use crate::error::PaymentError;
 use std::str::FromStr;
 use web3::contract::tokens::Tokenize;
 use web3::contract::Contract;
+use web3::ethabi::Token;
 use web3::transports::Http;
-use web3::types::{Address, U256};
+use web3::types::{Address, H256, U256};
 use web3::{Transport, Web3};
 
 lazy_static! {
@@ -21,6 +22,14 @@ lazy_static! {
     pub static ref ERC20_MULTI_CONTRACT_TEMPLATE: Contract<Http> = {
         prepare_contract_template(include_bytes!("../contracts/multi_transfer_erc20.json")).unwrap()
     };
+    pub static ref META_TRANSACTION_CONTRACT_TEMPLATE: Contract<Http> =
+        prepare_contract_template(include_bytes!("../contracts/meta_transaction.json")).unwrap();
+    pub static ref ERC20_PERMIT_CONTRACT_TEMPLATE: Contract<Http> =
+        prepare_contract_template(include_bytes!("../contracts/erc20_permit.json")).unwrap();
+    pub static ref DISPERSE_CONTRACT_TEMPLATE: Contract<Http> =
+        prepare_contract_template(include_bytes!("../contracts/disperse.json")).unwrap();
+    pub static ref EIP712_CONTRACT_TEMPLATE: Contract<Http> =
+        prepare_contract_template(include_bytes!("../contracts/eip712.json")).unwrap();
 }
 
 pub fn prepare_contract_template(json_abi: &[u8]) -> Result<Contract<Http>, PaymentError> {
@@ -49,13 +58,48 @@ where
         .and_then(|function| function.encode_input(&params.into_tokens()))
 }
 
-#[allow(dead_code)]
+pub fn contract_decode<T>(
+    contract: &Contract<T>,
+    func: &str,
+    data: &[u8],
+) -> Result<Vec<Token>, web3::ethabi::Error>
+where
+    T: Transport,
+{
+    let function = contract.abi().function(func)?;
+    if data.len() < 4 || data[..4] != function.short_signature() {
+        return Err(web3::ethabi::Error::InvalidData);
+    }
+    function.decode_input(&data[4..])
+}
+
+/// Find method name (as stored in tx method column) matching selector of given call data
+pub fn get_method_from_input(data: &[u8]) -> Option<String> {
+    if data.len() < 4 {
+        return None;
+    }
+    let templates: [(&str, &Contract<Http>); 5] = [
+        ("MULTI", &ERC20_MULTI_CONTRACT_TEMPLATE),
+        ("DISPERSE", &DISPERSE_CONTRACT_TEMPLATE),
+        ("META", &META_TRANSACTION_CONTRACT_TEMPLATE),
+        ("ERC20", &ERC20_PERMIT_CONTRACT_TEMPLATE),
+        ("ERC20", &ERC20_CONTRACT_TEMPLATE),
+    ];
+    templates.iter().find_map(|(prefix, contract)| {
+        contract
+            .abi()
+            .functions()
+            .find(|function| data[..4] == function.short_signature())
+            .map(|function| format!("{}.{}", prefix, function.name))
+    })
+}
+
 pub fn encode_erc20_balance_of(address: Address) -> Result<Vec<u8>, web3::ethabi::Error> {
-    contract_encode(
-        &ERC20_CONTRACT_TEMPLATE,
-        "balance_of",
-        (address.to_string(),),
-    )
+    contract_encode(&ERC20_CONTRACT_TEMPLATE, "balanceOf", (address,))
+}
+
+pub fn encode_erc20_decimals() -> Result<Vec<u8>, web3::ethabi::Error> {
+    contract_encode(&ERC20_CONTRACT_TEMPLATE, "decimals", ())
 }
 
 pub fn encode_erc20_transfer(
@@ -97,3 +141,124 @@ pub fn encode_multi_indirect_packed(
         (packed, sum),
     )
 }
+
+/// Number of receivers in golemTransferDirectPacked or golemTransferIndirectPacked call data
+pub fn decode_multi_packed_receivers_count(data: &[u8]) -> Option<usize> {
+    ["golemTransferDirectPacked", "golemTransferIndirectPacked"]
+        .iter()
+        .find_map(|func| contract_decode(&ERC20_MULTI_CONTRACT_TEMPLATE, func, data).ok())
+        .and_then(|tokens| match tokens.first() {
+            Some(Token::Array(packed)) => Some(packed.len()),
+            _ => None,
+        })
+}
+
+pub fn encode_disperse_ether(
+    recipients: Vec<Address>,
+    values: Vec<U256>,
+) -> Result<Vec<u8>, web3::ethabi::Error> {
+    contract_encode(
+        &DISPERSE_CONTRACT_TEMPLATE,
+        "disperseEther",
+        (recipients, values),
+    )
+}
+
+pub fn decode_disperse_ether(
+    data: &[u8],
+) -> Result<(Vec<Address>, Vec<U256>), web3::ethabi::Error> {
+    let tokens = contract_decode(&DISPERSE_CONTRACT_TEMPLATE, "disperseEther", data)?;
+    match tokens.as_slice() {
+        [Token::Array(recipients), Token::Array(values)] if recipients.len() == values.len() => {
+            let recipients = recipients
+                .iter()
+                .map(|t| t.clone().into_address())
+                .collect::<Option<Vec<_>>>();
+            let values = values
+                .iter()
+                .map(|t| t.clone().into_uint())
+                .collect::<Option<Vec<_>>>();
+            recipients
+                .zip(values)
+                .ok_or(web3::ethabi::Error::InvalidData)
+        }
+        _ => Err(web3::ethabi::Error::InvalidData),
+    }
+}
+
+pub fn encode_meta_transaction_get_nonce(user: Address) -> Result<Vec<u8>, web3::ethabi::Error> {
+    contract_encode(&META_TRANSACTION_CONTRACT_TEMPLATE, "getNonce", (user,))
+}
+
+pub fn encode_execute_meta_transaction(
+    user: Address,
+    function_signature: Vec<u8>,
+    sig_r: H256,
+    sig_s: H256,
+    sig_v: u8,
+) -> Result<Vec<u8>, web3::ethabi::Error> {
+    contract_encode(
+        &META_TRANSACTION_CONTRACT_TEMPLATE,
+        "executeMetaTransaction",
+        (user, function_signature, sig_r, sig_s, sig_v),
+    )
+}
+
+/// Returns user address, wrapped function call and signature v value
+pub fn decode_execute_meta_transaction(
+    data: &[u8],
+) -> Result<(Address, Vec<u8>, u8), web3::ethabi::Error> {
+    let tokens = contract_decode(
+        &META_TRANSACTION_CONTRACT_TEMPLATE,
+        "executeMetaTransaction",
+        data,
+    )?;
+    match tokens.as_slice() {
+        [Token::Address(user), Token::Bytes(function_signature), _, _, Token::Uint(sig_v)] => {
+            Ok((*user, function_signature.clone(), sig_v.low_u32() as u8))
+        }
+        _ => Err(web3::ethabi::Error::InvalidData),
+    }
+}
+
+pub fn encode_get_domain_separator() -> Result<Vec<u8>, web3::ethabi::Error> {
+    contract_encode(&EIP712_CONTRACT_TEMPLATE, "getDomainSeperator", ())
+}
+
+pub fn encode_erc20_permit_nonces(owner: Address) -> Result<Vec<u8>, web3::ethabi::Error> {
+    contract_encode(&ERC20_PERMIT_CONTRACT_TEMPLATE, "nonces", (owner,))
+}
+
+pub fn encode_erc20_domain_separator() -> Result<Vec<u8>, web3::ethabi::Error> {
+    contract_encode(&ERC20_PERMIT_CONTRACT_TEMPLATE, "DOMAIN_SEPARATOR", ())
+}
+
+#[allow(clippy::too_many_arguments)]
+pub fn encode_erc20_permit(
+    owner: Address,
+    spender: Address,
+    value: U256,
+    deadline: U256,
+    sig_v: u8,
+    sig_r: H256,
+    sig_s: H256,
+) -> Result<Vec<u8>, web3::ethabi::Error> {
+    contract_encode(
+        &ERC20_PERMIT_CONTRACT_TEMPLATE,
+        "permit",
+        (owner, spender, value, deadline, sig_v, sig_r, sig_s),
+    )
+}
+
+/// Returns owner, spender, value, deadline and signature v value
+pub fn decode_erc20_permit(
+    data: &[u8],
+) -> Result<(Address, Address, U256, U256, u8), web3::ethabi::Error> {
+    let tokens = contract_decode(&ERC20_PERMIT_CONTRACT_TEMPLATE, "permit", data)?;
+    match tokens.as_slice() {
+        [Token::Address(owner), Token::Address(spender), Token::Uint(value), Token::Uint(deadline), Token::Uint(sig_v), _, _] => {
+            Ok((*owner, *spender, *value, *deadline, sig_v.low_u32() as u8))
+        }
+        _ => Err(web3::ethabi::Error::InvalidData),
+    }
+}
@@ -1,13 +1,34 @@
+mod account_nonce_dao;
 mod allowance_dao;
 mod chain_transfer_dao;
 mod chain_tx_dao;
+mod operator_action_dao;
+mod recurring_payment_dao;
+mod screening_address_dao;
 mod token_transfer_dao;
+mod transfer_approval_dao;
+mod transfer_export_dao;
 mod transfer_in_dao;
 mod tx_dao;
 
+pub use account_nonce_dao::AccountNonceDao;
 pub use allowance_dao::AllowanceDao;
 pub use chain_transfer_dao::{ChainTransferDao, ChainTransferDaoExt};
 pub use chain_tx_dao::ChainTxDao;
-pub use token_transfer_dao::TokenTransferDao;
+pub use operator_action_dao::{
+    OperatorActionDao, OPERATOR_ACTIONS, OPERATOR_ACTION_CANCEL, OPERATOR_ACTION_HOLD,
+    OPERATOR_ACTION_MARK_FAILED, OPERATOR_ACTION_MARK_REPLACED, OPERATOR_ACTION_RELEASE,
+    OPERATOR_ACTION_REQUEUE, OPERATOR_ACTION_RETRY, OPERATOR_ACTION_SKIP,
+};
+pub use recurring_payment_dao::RecurringPaymentDao;
+pub use screening_address_dao::ScreeningAddressDao;
+pub use token_transfer_dao::{
+    TokenTransferDao, APPROVAL_STATE_APPROVED, APPROVAL_STATE_PENDING, APPROVAL_STATE_REJECTED,
+    TRANSFER_ERROR_DEADLINE_EXCEEDED,
+};
+pub use transfer_approval_dao::{
+    TransferApprovalDao, APPROVAL_DECISION_APPROVE, APPROVAL_DECISION_REJECT,
+};
+pub use transfer_export_dao::TransferExportDao;
 pub use transfer_in_dao::TransferInDao;
 pub use tx_dao::TxDao;
@@ -0,0 +1,16 @@
+use serde::Serialize;
+
+/// Nonce usage of sender account according to tx table
+#[derive(Serialize, sqlx::FromRow, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct AccountNonceDao {
+    pub from_addr: String,
+    pub chain_id: i64,
+    /// Highest nonce of confirmed transactions
+    pub last_confirmed_nonce: Option<i64>,
+    pub pending_count: i64,
+    pub pending_min_nonce: Option<i64>,
+    pub pending_max_nonce: Option<i64>,
+    /// Transactions queued, but not yet signed (without nonce)
+    pub unsigned_count: i64,
+}
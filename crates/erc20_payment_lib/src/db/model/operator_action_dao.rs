@@ -0,0 +1,44 @@
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+
+/// Stop processing transaction, its transfers are marked as failed
+pub const OPERATOR_ACTION_SKIP: &str = "skip";
+/// Keep transaction out of processing until released
+pub const OPERATOR_ACTION_HOLD: &str = "hold";
+pub const OPERATOR_ACTION_RELEASE: &str = "release";
+/// Return transfers of failed transaction to the queue
+pub const OPERATOR_ACTION_RETRY: &str = "retry";
+/// Drop transaction which was not sent yet, its transfers are marked as failed
+pub const OPERATOR_ACTION_CANCEL: &str = "cancel";
+
+/// Manual queue repairs, recorded as already applied for audit
+pub const OPERATOR_ACTION_MARK_FAILED: &str = "mark_failed";
+pub const OPERATOR_ACTION_MARK_REPLACED: &str = "mark_replaced";
+pub const OPERATOR_ACTION_REQUEUE: &str = "requeue";
+
+/// Actions which can be requested and are applied by the engine
+pub const OPERATOR_ACTIONS: &[&str] = &[
+    OPERATOR_ACTION_SKIP,
+    OPERATOR_ACTION_HOLD,
+    OPERATOR_ACTION_RELEASE,
+    OPERATOR_ACTION_RETRY,
+    OPERATOR_ACTION_CANCEL,
+];
+
+#[derive(Serialize, sqlx::FromRow, Debug, Clone, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub struct OperatorActionDao {
+    pub id: i64,
+    /// Not set for actions on transfers without transaction
+    pub tx_id: Option<i64>,
+    pub transfer_id: Option<i64>,
+    /// One of OPERATOR_ACTION_* constants
+    pub action: String,
+    /// Identity of the operator who requested the action
+    pub actor: String,
+    pub reason: String,
+    pub created_date: DateTime<Utc>,
+    /// Set when the engine applied the action
+    pub processed_date: Option<DateTime<Utc>>,
+    pub result: Option<String>,
+}
@@ -0,0 +1,22 @@
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+
+#[derive(Serialize, sqlx::FromRow, Debug, Clone, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub struct RecurringPaymentDao {
+    pub id: i64,
+    pub from_addr: String,
+    pub receiver_addr: String,
+    pub chain_id: i64,
+    pub token_addr: Option<String>,
+    pub token_amount: String,
+    /// Cron expression (with seconds field), exclusive with interval_secs
+    pub schedule: Option<String>,
+    pub interval_secs: Option<i64>,
+    pub start_date: DateTime<Utc>,
+    pub end_date: Option<DateTime<Utc>>,
+    /// Date of the last occurrence materialized as token transfer
+    pub last_occurrence: Option<DateTime<Utc>>,
+    pub active: bool,
+    pub created_date: DateTime<Utc>,
+}
@@ -0,0 +1,11 @@
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+
+#[derive(Serialize, sqlx::FromRow, Debug, Clone, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub struct ScreeningAddressDao {
+    pub id: i64,
+    pub address: String,
+    pub note: Option<String>,
+    pub created_date: DateTime<Utc>,
+}
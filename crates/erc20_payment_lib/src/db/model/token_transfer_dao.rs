@@ -1,5 +1,15 @@
+use chrono::{DateTime, Utc};
 use serde::Serialize;
 
+/// Transfer breached spending limits or matched approval rules and is held until approved
+pub const APPROVAL_STATE_PENDING: &str = "pending_approval";
+/// Transfer was approved and is not checked against spending limits and approval rules again
+pub const APPROVAL_STATE_APPROVED: &str = "approved";
+/// Transfer was rejected by operator, it is marked with error as well
+pub const APPROVAL_STATE_REJECTED: &str = "rejected";
+/// Error of transfer not sent before its deadline, matched by TRANSFER_FILTER_DEADLINE_EXCEEDED
+pub const TRANSFER_ERROR_DEADLINE_EXCEEDED: &str = "Deadline exceeded";
+
 #[derive(Serialize, sqlx::FromRow, Debug, Clone)]
 #[serde(rename_all = "camelCase")]
 pub struct TokenTransferDao {
@@ -13,4 +23,20 @@ pub struct TokenTransferDao {
     pub tx_id: Option<i64>,
     pub fee_paid: Option<String>,
     pub error: Option<String>,
+    pub created_date: Option<DateTime<Utc>>,
+    /// Transfer is not batched before this date
+    pub execute_after: Option<DateTime<Utc>>,
+    /// Transfer fails if it is not batched before this date
+    pub deadline: Option<DateTime<Utc>>,
+    /// Do not merge with other transfers to the same receiver
+    pub no_merge: bool,
+    /// Set when transfer breached spending limits, see APPROVAL_STATE_* constants
+    pub approval_state: Option<String>,
+    /// Previous failed attempt of the same transfer
+    pub retry_of: Option<i64>,
+    pub attempt: i64,
+    /// Failed transfer which is not retried anymore
+    pub dead_letter: bool,
+    /// Sender balance did not cover the transfer on last funds check before batching
+    pub waiting_for_funds: bool,
 }
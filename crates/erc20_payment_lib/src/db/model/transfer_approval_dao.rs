@@ -0,0 +1,18 @@
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+
+pub const APPROVAL_DECISION_APPROVE: &str = "approve";
+pub const APPROVAL_DECISION_REJECT: &str = "reject";
+
+#[derive(Serialize, sqlx::FromRow, Debug, Clone, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub struct TransferApprovalDao {
+    pub id: i64,
+    pub transfer_id: i64,
+    /// Identity of the operator who made the decision
+    pub approver: String,
+    /// One of APPROVAL_DECISION_* constants
+    pub decision: String,
+    pub reason: Option<String>,
+    pub created_date: DateTime<Utc>,
+}
@@ -0,0 +1,22 @@
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+
+/// Completed token transfer joined with its transaction
+#[derive(Serialize, sqlx::FromRow, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct TransferExportDao {
+    pub id: i64,
+    pub payment_id: Option<String>,
+    pub from_addr: String,
+    pub receiver_addr: String,
+    pub chain_id: i64,
+    pub token_addr: Option<String>,
+    pub token_amount: String,
+    /// Share of the transaction fee attributed to the transfer
+    pub fee_paid: Option<String>,
+    pub tx_id: i64,
+    pub tx_hash: Option<String>,
+    pub block_number: Option<i64>,
+    pub confirm_date: Option<DateTime<Utc>>,
+    pub tx_fee_paid: Option<String>,
+}
@@ -1,13 +1,23 @@
 mod allowance_ops;
 mod chain_transfer_ops;
 mod chain_tx_ops;
+mod operator_action_ops;
+mod recurring_payment_ops;
+mod screening_address_ops;
 mod token_transfer_ops;
+mod transfer_approval_ops;
+mod transfer_export_ops;
 mod transfer_in_ops;
 mod tx_ops;
 
 pub use allowance_ops::*;
 pub use chain_transfer_ops::*;
 pub use chain_tx_ops::*;
+pub use operator_action_ops::*;
+pub use recurring_payment_ops::*;
+pub use screening_address_ops::*;
 pub use token_transfer_ops::*;
+pub use transfer_approval_ops::*;
+pub use transfer_export_ops::*;
 pub use transfer_in_ops::*;
 pub use tx_ops::*;
@@ -111,6 +111,8 @@ owner = $1 AND
 token_addr = $2 AND
 spender = $3 AND
 chain_id = $4
+ORDER BY id DESC
+LIMIT 1
 ",
     )
     .bind(owner)
@@ -0,0 +1,115 @@
+use crate::db::model::*;
+use sqlx::SqlitePool;
+
+pub async fn insert_operator_action(
+    conn: &SqlitePool,
+    operator_action: &OperatorActionDao,
+) -> Result<OperatorActionDao, sqlx::Error> {
+    let res = sqlx::query_as::<_, OperatorActionDao>(
+        r"INSERT INTO operator_action
+(tx_id, transfer_id, action, actor, reason, created_date, processed_date, result)
+VALUES ($1, $2, $3, $4, $5, $6, $7, $8) RETURNING *;
+",
+    )
+    .bind(operator_action.tx_id)
+    .bind(operator_action.transfer_id)
+    .bind(&operator_action.action)
+    .bind(&operator_action.actor)
+    .bind(&operator_action.reason)
+    .bind(operator_action.created_date)
+    .bind(operator_action.processed_date)
+    .bind(&operator_action.result)
+    .fetch_one(conn)
+    .await?;
+    Ok(res)
+}
+
+pub async fn update_operator_action(
+    conn: &SqlitePool,
+    operator_action: &OperatorActionDao,
+) -> Result<OperatorActionDao, sqlx::Error> {
+    sqlx::query(
+        r"UPDATE operator_action SET
+processed_date = $2,
+result = $3
+WHERE id = $1
+",
+    )
+    .bind(operator_action.id)
+    .bind(operator_action.processed_date)
+    .bind(&operator_action.result)
+    .execute(conn)
+    .await?;
+    Ok(operator_action.clone())
+}
+
+/// Actions not applied by the engine yet, oldest first
+pub async fn get_unprocessed_operator_actions(
+    conn: &SqlitePool,
+) -> Result<Vec<OperatorActionDao>, sqlx::Error> {
+    let rows = sqlx::query_as::<_, OperatorActionDao>(
+        r"SELECT * FROM operator_action WHERE processed_date IS NULL ORDER BY id",
+    )
+    .fetch_all(conn)
+    .await?;
+    Ok(rows)
+}
+
+/// Actions of the transaction or all actions when tx id is not given, newest first
+pub async fn get_operator_actions(
+    conn: &SqlitePool,
+    tx_id: Option<i64>,
+    limit: Option<i64>,
+) -> Result<Vec<OperatorActionDao>, sqlx::Error> {
+    let rows = sqlx::query_as::<_, OperatorActionDao>(
+        r"SELECT * FROM operator_action WHERE $1 IS NULL OR tx_id = $1 ORDER BY id DESC LIMIT $2",
+    )
+    .bind(tx_id)
+    .bind(limit.unwrap_or(i64::MAX))
+    .fetch_all(conn)
+    .await?;
+    Ok(rows)
+}
+
+#[tokio::test]
+async fn operator_action_hold_test() -> sqlx::Result<()> {
+    use crate::db::create_sqlite_connection;
+    use crate::db::ops::{get_next_transactions_to_process, insert_tx};
+    use crate::transaction::create_eth_transfer;
+    use web3::types::{Address, U256};
+
+    let conn = create_sqlite_connection(None, true).await.unwrap();
+
+    let tx = insert_tx(
+        &conn,
+        &create_eth_transfer(
+            Address::from_low_u64_be(1),
+            Address::from_low_u64_be(2),
+            987789,
+            None,
+            U256::from(1),
+            U256::from(1),
+            U256::from(1),
+        ),
+    )
+    .await?;
+    let mut operator_action = OperatorActionDao {
+        id: 0,
+        tx_id: Some(tx.id),
+        transfer_id: None,
+        action: OPERATOR_ACTION_HOLD.to_string(),
+        actor: "test".to_string(),
+        reason: "test".to_string(),
+        created_date: chrono::Utc::now(),
+        processed_date: None,
+        result: None,
+    };
+    insert_operator_action(&conn, &operator_action).await?;
+    assert!(get_next_transactions_to_process(&conn, 1).await?.is_empty());
+
+    operator_action.action = OPERATOR_ACTION_RELEASE.to_string();
+    insert_operator_action(&conn, &operator_action).await?;
+    assert_eq!(get_next_transactions_to_process(&conn, 1).await?.len(), 1);
+    assert_eq!(get_unprocessed_operator_actions(&conn).await?.len(), 2);
+    Ok(())
+}
@@ -0,0 +1,88 @@
+use crate::db::model::*;
+use sqlx::SqlitePool;
+use sqlx_core::executor::Executor;
+use sqlx_core::sqlite::Sqlite;
+
+pub async fn insert_recurring_payment(
+    conn: &SqlitePool,
+    recurring_payment: &RecurringPaymentDao,
+) -> Result<RecurringPaymentDao, sqlx::Error> {
+    let res = sqlx::query_as::<_, RecurringPaymentDao>(
+        r"INSERT INTO recurring_payment
+(from_addr, receiver_addr, chain_id, token_addr, token_amount, schedule, interval_secs, start_date, end_date, last_occurrence, active, created_date)
+VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11, $12) RETURNING *;
+",
+    )
+    .bind(&recurring_payment.from_addr)
+    .bind(&recurring_payment.receiver_addr)
+    .bind(recurring_payment.chain_id)
+    .bind(&recurring_payment.token_addr)
+    .bind(&recurring_payment.token_amount)
+    .bind(&recurring_payment.schedule)
+    .bind(recurring_payment.interval_secs)
+    .bind(recurring_payment.start_date)
+    .bind(recurring_payment.end_date)
+    .bind(recurring_payment.last_occurrence)
+    .bind(recurring_payment.active)
+    .bind(recurring_payment.created_date)
+    .fetch_one(conn)
+    .await?;
+    Ok(res)
+}
+
+pub async fn update_recurring_payment<'c, E>(
+    executor: E,
+    recurring_payment: &RecurringPaymentDao,
+) -> Result<RecurringPaymentDao, sqlx::Error>
+where
+    E: Executor<'c, Database = Sqlite>,
+{
+    let _res = sqlx::query(
+        r"UPDATE recurring_payment SET
+end_date = $2,
+last_occurrence = $3,
+active = $4
+WHERE id = $1
+",
+    )
+    .bind(recurring_payment.id)
+    .bind(recurring_payment.end_date)
+    .bind(recurring_payment.last_occurrence)
+    .bind(recurring_payment.active)
+    .execute(executor)
+    .await?;
+    Ok(recurring_payment.clone())
+}
+
+pub async fn get_recurring_payment(
+    conn: &SqlitePool,
+    id: i64,
+) -> Result<Option<RecurringPaymentDao>, sqlx::Error> {
+    let row =
+        sqlx::query_as::<_, RecurringPaymentDao>(r"SELECT * FROM recurring_payment WHERE id = $1")
+            .bind(id)
+            .fetch_optional(conn)
+            .await?;
+    Ok(row)
+}
+
+pub async fn get_all_recurring_payments(
+    conn: &SqlitePool,
+) -> Result<Vec<RecurringPaymentDao>, sqlx::Error> {
+    let rows =
+        sqlx::query_as::<_, RecurringPaymentDao>(r"SELECT * FROM recurring_payment ORDER BY id")
+            .fetch_all(conn)
+            .await?;
+    Ok(rows)
+}
+
+pub async fn get_active_recurring_payments(
+    conn: &SqlitePool,
+) -> Result<Vec<RecurringPaymentDao>, sqlx::Error> {
+    let rows = sqlx::query_as::<_, RecurringPaymentDao>(
+        r"SELECT * FROM recurring_payment WHERE active = 1 ORDER BY id",
+    )
+    .fetch_all(conn)
+    .await?;
+    Ok(rows)
+}
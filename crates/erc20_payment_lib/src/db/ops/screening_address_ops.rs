@@ -0,0 +1,41 @@
+use crate::db::model::*;
+use sqlx::SqlitePool;
+
+pub async fn insert_screening_address(
+    conn: &SqlitePool,
+    screening_address: &ScreeningAddressDao,
+) -> Result<ScreeningAddressDao, sqlx::Error> {
+    let res = sqlx::query_as::<_, ScreeningAddressDao>(
+        r"INSERT INTO screening_address
+(address, note, created_date)
+VALUES ($1, $2, $3) RETURNING *;
+",
+    )
+    .bind(&screening_address.address)
+    .bind(&screening_address.note)
+    .bind(screening_address.created_date)
+    .fetch_one(conn)
+    .await?;
+    Ok(res)
+}
+
+pub async fn delete_screening_address(
+    conn: &SqlitePool,
+    address: &str,
+) -> Result<u64, sqlx::Error> {
+    let res = sqlx::query(r"DELETE FROM screening_address WHERE address = $1")
+        .bind(address)
+        .execute(conn)
+        .await?;
+    Ok(res.rows_affected())
+}
+
+pub async fn get_all_screening_addresses(
+    conn: &SqlitePool,
+) -> Result<Vec<ScreeningAddressDao>, sqlx::Error> {
+    let rows =
+        sqlx::query_as::<_, ScreeningAddressDao>(r"SELECT * FROM screening_address ORDER BY id")
+            .fetch_all(conn)
+            .await?;
+    Ok(rows)
+}
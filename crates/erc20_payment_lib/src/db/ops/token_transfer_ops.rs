@@ -3,14 +3,17 @@ use sqlx::SqlitePool;
 use sqlx_core::executor::Executor;
 use sqlx_core::sqlite::Sqlite;
 
-pub async fn insert_token_transfer(
-    conn: &SqlitePool,
+pub async fn insert_token_transfer<'c, E>(
+    executor: E,
     token_transfer: &TokenTransferDao,
-) -> Result<TokenTransferDao, sqlx::Error> {
+) -> Result<TokenTransferDao, sqlx::Error>
+where
+    E: Executor<'c, Database = Sqlite>,
+{
     let res = sqlx::query_as::<_, TokenTransferDao>(
         r"INSERT INTO token_transfer
-(payment_id, from_addr, receiver_addr, chain_id, token_addr, token_amount, tx_id, fee_paid, error)
-VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9) RETURNING *;
+(payment_id, from_addr, receiver_addr, chain_id, token_addr, token_amount, tx_id, fee_paid, error, created_date, execute_after, deadline, no_merge, approval_state, retry_of, attempt, dead_letter, waiting_for_funds)
+VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11, $12, $13, $14, $15, $16, $17, $18) RETURNING *;
 ",
     )
     .bind(&token_transfer.payment_id)
@@ -22,7 +25,16 @@ VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9) RETURNING *;
     .bind(token_transfer.tx_id)
     .bind(&token_transfer.fee_paid)
     .bind(&token_transfer.error)
-    .fetch_one(conn)
+    .bind(token_transfer.created_date)
+    .bind(token_transfer.execute_after)
+    .bind(token_transfer.deadline)
+    .bind(token_transfer.no_merge)
+    .bind(&token_transfer.approval_state)
+    .bind(token_transfer.retry_of)
+    .bind(token_transfer.attempt)
+    .bind(token_transfer.dead_letter)
+    .bind(token_transfer.waiting_for_funds)
+    .fetch_one(executor)
     .await?;
     Ok(res)
 }
@@ -44,7 +56,12 @@ token_addr = $6,
 token_amount = $7,
 tx_id = $8,
 fee_paid = $9,
-error = $10
+error = $10,
+execute_after = $11,
+deadline = $12,
+approval_state = $13,
+dead_letter = $14,
+waiting_for_funds = $15
 WHERE id = $1
 ",
     )
@@ -58,6 +75,11 @@ WHERE id = $1
     .bind(token_transfer.tx_id)
     .bind(&token_transfer.fee_paid)
     .bind(&token_transfer.error)
+    .bind(token_transfer.execute_after)
+    .bind(token_transfer.deadline)
+    .bind(&token_transfer.approval_state)
+    .bind(token_transfer.dead_letter)
+    .bind(token_transfer.waiting_for_funds)
     .execute(executor)
     .await?;
     Ok(token_transfer.clone())
@@ -84,6 +106,95 @@ pub async fn get_pending_token_transfers(
         r"SELECT * FROM token_transfer
 WHERE tx_id is null
 AND error is null
+AND (execute_after is null OR execute_after <= $1)
+AND (approval_state is null OR approval_state = $2)
+",
+    )
+    .bind(chrono::Utc::now())
+    .bind(APPROVAL_STATE_APPROVED)
+    .fetch_all(conn)
+    .await?;
+    Ok(rows)
+}
+
+/// Not sent transfers past their deadline, including held, not approved and not yet due ones
+pub async fn get_token_transfers_past_deadline(
+    conn: &SqlitePool,
+) -> Result<Vec<TokenTransferDao>, sqlx::Error> {
+    let rows = sqlx::query_as::<_, TokenTransferDao>(
+        r"SELECT * FROM token_transfer
+WHERE tx_id is null
+AND error is null
+AND deadline is not null
+AND deadline < $1
+ORDER BY id
+",
+    )
+    .bind(chrono::Utc::now())
+    .fetch_all(conn)
+    .await?;
+    Ok(rows)
+}
+
+pub async fn get_token_transfer(
+    conn: &SqlitePool,
+    id: i64,
+) -> Result<Option<TokenTransferDao>, sqlx::Error> {
+    let row = sqlx::query_as::<_, TokenTransferDao>(r"SELECT * FROM token_transfer WHERE id = $1")
+        .bind(id)
+        .fetch_optional(conn)
+        .await?;
+    Ok(row)
+}
+
+pub async fn get_token_transfers_pending_approval(
+    conn: &SqlitePool,
+) -> Result<Vec<TokenTransferDao>, sqlx::Error> {
+    let rows = sqlx::query_as::<_, TokenTransferDao>(
+        r"SELECT * FROM token_transfer WHERE approval_state = $1 AND error is null ORDER BY id",
+    )
+    .bind(APPROVAL_STATE_PENDING)
+    .fetch_all(conn)
+    .await?;
+    Ok(rows)
+}
+
+/// Check if sender already sent (or is sending) anything to the receiver
+pub async fn has_transfers_to_receiver(
+    conn: &SqlitePool,
+    from_addr: &str,
+    chain_id: i64,
+    receiver_addr: &str,
+) -> Result<bool, sqlx::Error> {
+    let count = sqlx::query_scalar::<_, i64>(
+        r"SELECT COUNT(*) FROM token_transfer
+WHERE tx_id is not null
+AND error is null
+AND from_addr = $1
+AND chain_id = $2
+AND receiver_addr = $3
+",
+    )
+    .bind(from_addr)
+    .bind(chain_id)
+    .bind(receiver_addr)
+    .fetch_one(conn)
+    .await?;
+    Ok(count > 0)
+}
+
+/// Failed transfers of finished transactions, which were not retried yet
+pub async fn get_token_transfers_to_retry(
+    conn: &SqlitePool,
+) -> Result<Vec<TokenTransferDao>, sqlx::Error> {
+    let rows = sqlx::query_as::<_, TokenTransferDao>(
+        r"SELECT tt.* FROM token_transfer tt
+LEFT JOIN tx ON tt.tx_id = tx.id
+WHERE tt.error is not null
+AND tt.dead_letter = 0
+AND (tx.id is null OR tx.processing = 0)
+AND NOT EXISTS (SELECT 1 FROM token_transfer r WHERE r.retry_of = tt.id)
+ORDER BY tt.id
 ",
     )
     .fetch_all(conn)
@@ -91,6 +202,110 @@ AND error is null
     Ok(rows)
 }
 
+pub async fn get_token_transfer_retry(
+    conn: &SqlitePool,
+    id: i64,
+) -> Result<Option<TokenTransferDao>, sqlx::Error> {
+    let row =
+        sqlx::query_as::<_, TokenTransferDao>(r"SELECT * FROM token_transfer WHERE retry_of = $1")
+            .bind(id)
+            .fetch_optional(conn)
+            .await?;
+    Ok(row)
+}
+
+pub async fn get_token_transfer_by_payment_id(
+    conn: &SqlitePool,
+    payment_id: &str,
+) -> Result<Option<TokenTransferDao>, sqlx::Error> {
+    let row = sqlx::query_as::<_, TokenTransferDao>(
+        r"SELECT * FROM token_transfer WHERE payment_id = $1",
+    )
+    .bind(payment_id)
+    .fetch_optional(conn)
+    .await?;
+    Ok(row)
+}
+
+/// Amounts of transfers already assigned to transactions which are not finished yet
+pub async fn get_reserved_token_amounts(
+    conn: &SqlitePool,
+    from_addr: &str,
+    chain_id: i64,
+    token_addr: Option<&str>,
+) -> Result<Vec<String>, sqlx::Error> {
+    let rows = sqlx::query_scalar::<_, String>(
+        r"SELECT tt.token_amount FROM token_transfer tt
+JOIN tx ON tt.tx_id = tx.id
+WHERE tx.processing > 0
+AND tt.from_addr = $1
+AND tt.chain_id = $2
+AND tt.token_addr IS $3
+",
+    )
+    .bind(from_addr)
+    .bind(chain_id)
+    .bind(token_addr)
+    .fetch_all(conn)
+    .await?;
+    Ok(rows)
+}
+
+/// Amounts of transfers waiting in queue, not assigned to any transaction yet
+pub async fn get_queued_token_amounts(
+    conn: &SqlitePool,
+    from_addr: &str,
+    chain_id: i64,
+    token_addr: Option<&str>,
+) -> Result<Vec<String>, sqlx::Error> {
+    let rows = sqlx::query_scalar::<_, String>(
+        r"SELECT token_amount FROM token_transfer
+WHERE tx_id IS NULL
+AND error IS NULL
+AND from_addr = $1
+AND chain_id = $2
+AND token_addr IS $3
+",
+    )
+    .bind(from_addr)
+    .bind(chain_id)
+    .bind(token_addr)
+    .fetch_all(conn)
+    .await?;
+    Ok(rows)
+}
+
+/// Amounts of transfers assigned to transactions created since the given date,
+/// optionally only the ones sent to the given receiver
+pub async fn get_sent_token_amounts_since(
+    conn: &SqlitePool,
+    from_addr: &str,
+    chain_id: i64,
+    token_addr: Option<&str>,
+    receiver_addr: Option<&str>,
+    since: chrono::DateTime<chrono::Utc>,
+) -> Result<Vec<String>, sqlx::Error> {
+    let rows = sqlx::query_scalar::<_, String>(
+        r"SELECT tt.token_amount FROM token_transfer tt
+JOIN tx ON tt.tx_id = tx.id
+WHERE tt.error is null
+AND tx.created_date >= $1
+AND tt.from_addr = $2
+AND tt.chain_id = $3
+AND tt.token_addr IS $4
+AND ($5 IS NULL OR tt.receiver_addr = $5)
+",
+    )
+    .bind(since)
+    .bind(from_addr)
+    .bind(chain_id)
+    .bind(token_addr)
+    .bind(receiver_addr)
+    .fetch_all(conn)
+    .await?;
+    Ok(rows)
+}
+
 pub async fn get_token_transfers_by_tx<'c, E>(
     executor: E,
     tx_id: i64,
@@ -110,6 +325,29 @@ pub const TRANSFER_FILTER_ALL: &str = "(id >= 0)";
 pub const TRANSFER_FILTER_QUEUED: &str = "(tx_id is null AND error is null)";
 pub const TRANSFER_FILTER_PROCESSING: &str = "(tx_id is not null AND fee_paid is null)";
 pub const TRANSFER_FILTER_DONE: &str = "(fee_paid is not null)";
+pub const TRANSFER_FILTER_FAILED: &str = "(error is not null)";
+pub const TRANSFER_FILTER_DEAD_LETTER: &str = "(dead_letter = 1)";
+pub const TRANSFER_FILTER_WAITING_FOR_FUNDS: &str =
+    "(waiting_for_funds = 1 AND tx_id is null AND error is null)";
+/// Has to match TRANSFER_ERROR_DEADLINE_EXCEEDED
+pub const TRANSFER_FILTER_DEADLINE_EXCEEDED: &str = "(error = 'Deadline exceeded')";
+
+pub async fn get_token_transfers(
+    conn: &SqlitePool,
+    transfer_filter: Option<&str>,
+    limit: Option<i64>,
+) -> Result<Vec<TokenTransferDao>, sqlx::Error> {
+    let transfer_filter = transfer_filter.unwrap_or(TRANSFER_FILTER_ALL);
+    let limit = limit.unwrap_or(i64::MAX);
+    let rows = sqlx::query_as::<_, TokenTransferDao>(
+        format!(r"SELECT * FROM token_transfer WHERE {transfer_filter} ORDER BY id DESC LIMIT $1")
+            .as_str(),
+    )
+    .bind(limit)
+    .fetch_all(conn)
+    .await?;
+    Ok(rows)
+}
 
 pub async fn get_transfer_count(
     conn: &SqlitePool,
@@ -149,3 +387,29 @@ pub async fn get_transfer_count(
 
     Ok(count as usize)
 }
+
+#[tokio::test]
+async fn token_transfer_schedule_test() -> sqlx::Result<()> {
+    use crate::db::create_sqlite_connection;
+    use crate::transaction::create_token_transfer;
+    use web3::types::{Address, U256};
+
+    let conn = create_sqlite_connection(None, true).await.unwrap();
+
+    let mut token_transfer = create_token_transfer(
+        Address::from_low_u64_be(1),
+        Address::from_low_u64_be(2),
+        987789,
+        None,
+        None,
+        U256::from(1),
+    );
+    let due = insert_token_transfer(&conn, &token_transfer).await?;
+    token_transfer.execute_after = Some(chrono::Utc::now() + chrono::Duration::days(1));
+    insert_token_transfer(&conn, &token_transfer).await?;
+
+    let pending = get_pending_token_transfers(&conn).await?;
+    assert_eq!(pending.len(), 1);
+    assert_eq!(pending[0].id, due.id);
+    Ok(())
+}
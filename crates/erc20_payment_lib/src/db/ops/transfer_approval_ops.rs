@@ -0,0 +1,35 @@
+use crate::db::model::*;
+use sqlx::SqlitePool;
+
+pub async fn insert_transfer_approval(
+    conn: &SqlitePool,
+    transfer_approval: &TransferApprovalDao,
+) -> Result<TransferApprovalDao, sqlx::Error> {
+    let res = sqlx::query_as::<_, TransferApprovalDao>(
+        r"INSERT INTO transfer_approval
+(transfer_id, approver, decision, reason, created_date)
+VALUES ($1, $2, $3, $4, $5) RETURNING *;
+",
+    )
+    .bind(transfer_approval.transfer_id)
+    .bind(&transfer_approval.approver)
+    .bind(&transfer_approval.decision)
+    .bind(&transfer_approval.reason)
+    .bind(transfer_approval.created_date)
+    .fetch_one(conn)
+    .await?;
+    Ok(res)
+}
+
+pub async fn get_transfer_approvals(
+    conn: &SqlitePool,
+    transfer_id: i64,
+) -> Result<Vec<TransferApprovalDao>, sqlx::Error> {
+    let rows = sqlx::query_as::<_, TransferApprovalDao>(
+        r"SELECT * FROM transfer_approval WHERE transfer_id = $1 ORDER BY id",
+    )
+    .bind(transfer_id)
+    .fetch_all(conn)
+    .await?;
+    Ok(rows)
+}
@@ -0,0 +1,49 @@
+use crate::db::model::*;
+use chrono::{DateTime, Utc};
+use sqlx::SqlitePool;
+
+#[derive(Debug, Clone, Default)]
+pub struct TransferExportFilter {
+    /// Transfers confirmed at or after this date
+    pub since: Option<DateTime<Utc>>,
+    /// Transfers confirmed before this date
+    pub until: Option<DateTime<Utc>>,
+    pub from_addr: Option<String>,
+    pub token_addr: Option<String>,
+    /// Only native currency transfers
+    pub native_only: bool,
+    /// SQL LIKE pattern, i.e. recurring-3-%
+    pub payment_id: Option<String>,
+}
+
+pub async fn get_transfers_for_export(
+    conn: &SqlitePool,
+    filter: &TransferExportFilter,
+) -> Result<Vec<TransferExportDao>, sqlx::Error> {
+    let rows = sqlx::query_as::<_, TransferExportDao>(
+        r"SELECT tt.id, tt.payment_id, tt.from_addr, tt.receiver_addr, tt.chain_id, tt.token_addr,
+tt.token_amount, tt.fee_paid, tx.id as tx_id, tx.tx_hash, tx.block_number, tx.confirm_date,
+tx.fee_paid as tx_fee_paid
+FROM token_transfer tt
+JOIN tx ON tt.tx_id = tx.id
+WHERE tt.fee_paid is not null
+AND tt.error is null
+AND ($1 IS NULL OR tx.confirm_date >= $1)
+AND ($2 IS NULL OR tx.confirm_date < $2)
+AND ($3 IS NULL OR tt.from_addr = $3)
+AND ($4 IS NULL OR tt.token_addr = $4)
+AND ($5 = 0 OR tt.token_addr IS NULL)
+AND ($6 IS NULL OR tt.payment_id LIKE $6)
+ORDER BY tx.confirm_date, tt.id
+",
+    )
+    .bind(filter.since)
+    .bind(filter.until)
+    .bind(&filter.from_addr)
+    .bind(&filter.token_addr)
+    .bind(filter.native_only)
+    .bind(&filter.payment_id)
+    .fetch_all(conn)
+    .await?;
+    Ok(rows)
+}
@@ -8,6 +8,15 @@ pub const TRANSACTION_FILTER_PROCESSING: &str = "processing > 0 AND first_proces
 pub const TRANSACTION_FILTER_TO_PROCESS: &str = "processing > 0";
 pub const TRANSACTION_FILTER_ALL: &str = "id >= 0";
 pub const TRANSACTION_FILTER_DONE: &str = "processing = 0";
+/// Last hold operator action of the transaction is not followed by release
+pub const TRANSACTION_FILTER_HELD: &str = "processing > 0 AND EXISTS (
+SELECT 1 FROM operator_action hold WHERE hold.tx_id = tx.id AND hold.action = 'hold'
+AND NOT EXISTS (SELECT 1 FROM operator_action rel WHERE rel.tx_id = tx.id AND rel.action = 'release' AND rel.id > hold.id)
+)";
+pub const TRANSACTION_FILTER_TO_PROCESS_NOT_HELD: &str = "processing > 0 AND NOT EXISTS (
+SELECT 1 FROM operator_action hold WHERE hold.tx_id = tx.id AND hold.action = 'hold'
+AND NOT EXISTS (SELECT 1 FROM operator_action rel WHERE rel.tx_id = tx.id AND rel.action = 'release' AND rel.id > hold.id)
+)";
 pub const TRANSACTION_ORDER_BY_CREATE_DATE: &str = "created_date ASC";
 pub const TRANSACTION_ORDER_BY_FIRST_PROCESSED_DATE_DESC: &str = "first_processed DESC";
 
@@ -39,6 +48,24 @@ pub async fn get_transaction(conn: &SqlitePool, tx_id: i64) -> Result<TxDao, sql
     Ok(row)
 }
 
+pub async fn get_account_nonces(conn: &SqlitePool) -> Result<Vec<AccountNonceDao>, sqlx::Error> {
+    let rows = sqlx::query_as::<_, AccountNonceDao>(
+        r"SELECT from_addr, chain_id,
+MAX(CASE WHEN processing = 0 AND confirm_date IS NOT NULL THEN nonce END) as last_confirmed_nonce,
+COUNT(CASE WHEN processing > 0 AND nonce IS NOT NULL THEN 1 END) as pending_count,
+MIN(CASE WHEN processing > 0 THEN nonce END) as pending_min_nonce,
+MAX(CASE WHEN processing > 0 THEN nonce END) as pending_max_nonce,
+COUNT(CASE WHEN processing > 0 AND nonce IS NULL THEN 1 END) as unsigned_count
+FROM tx
+GROUP BY from_addr, chain_id
+ORDER BY chain_id, from_addr
+",
+    )
+    .fetch_all(conn)
+    .await?;
+    Ok(rows)
+}
+
 pub async fn get_transaction_count(
     conn: &SqlitePool,
     transaction_filter: Option<&str>,
@@ -52,13 +79,38 @@ pub async fn get_transaction_count(
     Ok(count as usize)
 }
 
+/// Average gas limit of the last transactions confirmed on chain for the account
+pub async fn get_average_gas_limit(
+    conn: &SqlitePool,
+    from_addr: &str,
+    chain_id: i64,
+    last_count: i64,
+) -> Result<Option<i64>, sqlx::Error> {
+    let avg = sqlx::query_scalar::<_, Option<i64>>(
+        r"SELECT CAST(AVG(gas_limit) AS INTEGER) FROM (
+SELECT gas_limit FROM tx
+WHERE from_addr = $1
+AND chain_id = $2
+AND confirm_date IS NOT NULL
+AND gas_limit IS NOT NULL
+ORDER BY id DESC LIMIT $3
+)",
+    )
+    .bind(from_addr)
+    .bind(chain_id)
+    .bind(last_count)
+    .fetch_one(conn)
+    .await?;
+    Ok(avg)
+}
+
 pub async fn get_next_transactions_to_process(
     conn: &SqlitePool,
     limit: i64,
 ) -> Result<Vec<TxDao>, sqlx::Error> {
     get_transactions(
         conn,
-        Some(TRANSACTION_FILTER_TO_PROCESS),
+        Some(TRANSACTION_FILTER_TO_PROCESS_NOT_HELD),
         Some(limit),
         Some(TRANSACTION_ORDER_BY_CREATE_DATE),
     )
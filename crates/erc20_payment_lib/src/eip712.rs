@@ -0,0 +1,70 @@
+use lazy_static::lazy_static;
+use web3::ethabi::{encode, Token};
+use web3::signing::keccak256;
+use web3::types::{Address, H256, U256};
+
+lazy_static! {
+    pub static ref META_TRANSACTION_TYPE_HASH: H256 = H256::from(keccak256(
+        b"MetaTransaction(uint256 nonce,address from,bytes functionSignature)"
+    ));
+    pub static ref PERMIT_TYPE_HASH: H256 = H256::from(keccak256(
+        b"Permit(address owner,address spender,uint256 value,uint256 nonce,uint256 deadline)"
+    ));
+}
+
+/// Final EIP-712 hash to sign: keccak256("\x19\x01" ‖ domainSeparator ‖ hashStruct(message))
+pub fn typed_data_hash(domain_separator: H256, struct_hash: H256) -> H256 {
+    let mut data = Vec::with_capacity(2 + 32 + 32);
+    data.extend_from_slice(b"\x19\x01");
+    data.extend_from_slice(domain_separator.as_bytes());
+    data.extend_from_slice(struct_hash.as_bytes());
+    H256::from(keccak256(&data))
+}
+
+/// Struct hash of MetaTransaction as defined in NativeMetaTransaction contract (used by GLM on Polygon)
+pub fn meta_transaction_struct_hash(nonce: U256, from: Address, function_signature: &[u8]) -> H256 {
+    H256::from(keccak256(&encode(&[
+        Token::FixedBytes(META_TRANSACTION_TYPE_HASH.as_bytes().to_vec()),
+        Token::Uint(nonce),
+        Token::Address(from),
+        Token::FixedBytes(keccak256(function_signature).to_vec()),
+    ])))
+}
+
+/// Struct hash of EIP-2612 Permit
+pub fn permit_struct_hash(
+    owner: Address,
+    spender: Address,
+    value: U256,
+    nonce: U256,
+    deadline: U256,
+) -> H256 {
+    H256::from(keccak256(&encode(&[
+        Token::FixedBytes(PERMIT_TYPE_HASH.as_bytes().to_vec()),
+        Token::Address(owner),
+        Token::Address(spender),
+        Token::Uint(value),
+        Token::Uint(nonce),
+        Token::Uint(deadline),
+    ])))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::str::FromStr;
+
+    #[test]
+    fn test_meta_transaction_type_hash() {
+        assert_eq!(
+            *META_TRANSACTION_TYPE_HASH,
+            H256::from_str("0x23d10def3caacba2e4042e0c75d44a42d2558aabcf5ce951d0642a8032e1e653")
+                .unwrap()
+        );
+        assert_eq!(
+            *PERMIT_TYPE_HASH,
+            H256::from_str("0x6e71edae12b1b97f4d1f60370fef10105fa2faae0126114a169c64845d6126c9")
+                .unwrap()
+        );
+    }
+}
@@ -6,5 +6,8 @@ pub struct AllowanceRequest {
     pub token_addr: String,
     pub spender_addr: String,
     pub chain_id: i64,
+    /// Amount to approve
     pub amount: U256,
+    /// Minimum allowance required to process the batch
+    pub minimum: U256,
 }
@@ -1,4 +1,4 @@
-use super::{CustomError, TransactionFailedError};
+use super::{CustomError, GasEstimationError, TransactionFailedError};
 use crate::error::allowance::AllowanceRequest;
 use crate::utils::ConversionError;
 use rustc_hex::FromHexError;
@@ -14,6 +14,7 @@ pub enum ErrorBag {
     IoError(std::io::Error),
     CustomError(CustomError),
     TransactionFailedError(TransactionFailedError),
+    GasEstimationError(GasEstimationError),
     SQLxError(sqlx::Error),
     SQLxMigrateError(sqlx::migrate::MigrateError),
     EthAbiError(web3::ethabi::Error),
@@ -33,6 +34,9 @@ impl Display for ErrorBag {
             ErrorBag::TransactionFailedError(transaction_failed_error) => {
                 write!(f, "{transaction_failed_error}")
             }
+            ErrorBag::GasEstimationError(gas_estimation_error) => {
+                write!(f, "{gas_estimation_error}")
+            }
             ErrorBag::SQLxError(sqlx_error) => write!(f, "{sqlx_error:?}"),
             ErrorBag::SQLxMigrateError(sqlx_migrate_error) => write!(f, "{sqlx_migrate_error:?}"),
             ErrorBag::EthAbiError(eth_abi_error) => write!(f, "{eth_abi_error:?}"),
@@ -71,6 +75,12 @@ impl From<TransactionFailedError> for ErrorBag {
     }
 }
 
+impl From<GasEstimationError> for ErrorBag {
+    fn from(err: GasEstimationError) -> Self {
+        ErrorBag::GasEstimationError(err)
+    }
+}
+
 impl From<sqlx::Error> for ErrorBag {
     fn from(err: sqlx::Error) -> Self {
         ErrorBag::SQLxError(err)
@@ -39,3 +39,24 @@ impl Display for TransactionFailedError {
         write!(f, "TransactionFailedError: {}", self.message)
     }
 }
+
+/// Gas estimation of the transaction failed, the transaction was not sent
+#[derive(Debug)]
+pub struct GasEstimationError {
+    pub message: String,
+}
+
+impl GasEstimationError {
+    pub fn new(message: &str) -> GasEstimationError {
+        GasEstimationError {
+            message: message.to_string(),
+        }
+    }
+}
+impl Error for GasEstimationError {}
+
+impl Display for GasEstimationError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "GasEstimationError: {}", self.message)
+    }
+}
@@ -1,10 +1,12 @@
 mod bag;
 mod custom;
+mod runtime;
 mod wrapped;
 
 pub use allowance::AllowanceRequest;
 pub use bag::ErrorBag;
-pub use custom::{CustomError, TransactionFailedError};
+pub use custom::{CustomError, GasEstimationError, TransactionFailedError};
+pub use runtime::PaymentRuntimeError;
 pub use wrapped::PaymentError;
 
 mod allowance;
@@ -0,0 +1,55 @@
+use super::PaymentError;
+use std::error::Error;
+use std::fmt::Display;
+use web3::types::Address;
+
+/// Errors returned by high level PaymentRuntime API
+#[derive(Debug)]
+pub enum PaymentRuntimeError {
+    ChainNotFound(i64),
+    /// Sender is not one of the loaded private keys
+    SenderNotFound(Address),
+    ReceiverRejected {
+        receiver: Address,
+        reason: String,
+    },
+    TransferNotFound(i64),
+    /// Transfer failed and will not be retried
+    TransferFailed {
+        id: i64,
+        error: String,
+    },
+    /// Payment engine is not running anymore
+    EngineStopped,
+    Internal(PaymentError),
+}
+
+impl Error for PaymentRuntimeError {}
+
+impl Display for PaymentRuntimeError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            PaymentRuntimeError::ChainNotFound(chain_id) => {
+                write!(f, "Chain {chain_id} not found in setup")
+            }
+            PaymentRuntimeError::SenderNotFound(sender) => {
+                write!(f, "No private key loaded for sender {sender:#x}")
+            }
+            PaymentRuntimeError::ReceiverRejected { receiver, reason } => {
+                write!(f, "Receiver {receiver:#x} rejected: {reason}")
+            }
+            PaymentRuntimeError::TransferNotFound(id) => write!(f, "Transfer {id} not found"),
+            PaymentRuntimeError::TransferFailed { id, error } => {
+                write!(f, "Transfer {id} failed: {error}")
+            }
+            PaymentRuntimeError::EngineStopped => write!(f, "Payment engine is not running"),
+            PaymentRuntimeError::Internal(payment_error) => write!(f, "{payment_error}"),
+        }
+    }
+}
+
+impl From<PaymentError> for PaymentRuntimeError {
+    fn from(err: PaymentError) -> Self {
+        PaymentRuntimeError::Internal(err)
+    }
+}
@@ -1,11 +1,15 @@
-use crate::contracts::encode_erc20_allowance;
+use crate::contracts::{
+    encode_erc20_allowance, encode_erc20_balance_of, encode_erc20_decimals,
+    encode_erc20_domain_separator, encode_erc20_permit_nonces, encode_get_domain_separator,
+    encode_meta_transaction_get_nonce,
+};
 use crate::error::*;
 use crate::{err_custom_create, err_from};
 use secp256k1::{PublicKey, SecretKey};
 use sha3::Digest;
 use sha3::Keccak256;
 use web3::transports::Http;
-use web3::types::{Address, Bytes, CallRequest, U256};
+use web3::types::{Address, Bytes, CallRequest, H256, U256};
 use web3::Web3;
 
 pub async fn get_transaction_count(
@@ -78,6 +82,172 @@ pub async fn check_allowance(
     Ok(allowance)
 }
 
+async fn call_contract_view(
+    web3: &Web3<Http>,
+    contract: Address,
+    data: Vec<u8>,
+) -> Result<Bytes, PaymentError> {
+    let call_request = CallRequest {
+        from: None,
+        to: Some(contract),
+        gas: None,
+        gas_price: None,
+        value: None,
+        data: Some(Bytes(data)),
+        transaction_type: None,
+        access_list: None,
+        max_fee_per_gas: None,
+        max_priority_fee_per_gas: None,
+    };
+    web3.eth()
+        .call(call_request, None)
+        .await
+        .map_err(err_from!())
+}
+
+/// Token balance of the address, native currency balance when token is None
+pub async fn get_balance(
+    web3: &Web3<Http>,
+    token: Option<Address>,
+    address: Address,
+) -> Result<U256, PaymentError> {
+    match token {
+        Some(token) => {
+            let res = call_contract_view(
+                web3,
+                token,
+                encode_erc20_balance_of(address).map_err(err_from!())?,
+            )
+            .await?;
+            if res.0.len() != 32 {
+                return Err(err_custom_create!(
+                    "Invalid response from ERC20 balanceOf {:?}",
+                    res
+                ));
+            };
+            Ok(U256::from_big_endian(&res.0))
+        }
+        None => web3.eth().balance(address, None).await.map_err(err_from!()),
+    }
+}
+
+/// Number of decimals of the ERC20 token
+pub async fn get_token_decimals(web3: &Web3<Http>, token: Address) -> Result<u32, PaymentError> {
+    let res =
+        call_contract_view(web3, token, encode_erc20_decimals().map_err(err_from!())?).await?;
+    if res.0.len() != 32 {
+        return Err(err_custom_create!(
+            "Invalid response from ERC20 decimals {:?}",
+            res
+        ));
+    };
+    let decimals = U256::from_big_endian(&res.0);
+    if decimals > U256::from(18) {
+        return Err(err_custom_create!(
+            "Token {:#x} has {} decimals, more than 18 are not supported",
+            token,
+            decimals
+        ));
+    }
+    Ok(decimals.as_u32())
+}
+
+pub async fn get_meta_transaction_nonce(
+    web3: &Web3<Http>,
+    token: Address,
+    user: Address,
+) -> Result<U256, PaymentError> {
+    let res = call_contract_view(
+        web3,
+        token,
+        encode_meta_transaction_get_nonce(user).map_err(err_from!())?,
+    )
+    .await?;
+    if res.0.len() != 32 {
+        return Err(err_custom_create!(
+            "Invalid response from meta transaction getNonce {:?}",
+            res
+        ));
+    };
+    Ok(U256::from_big_endian(&res.0))
+}
+
+pub async fn get_domain_separator(web3: &Web3<Http>, token: Address) -> Result<H256, PaymentError> {
+    let res = call_contract_view(
+        web3,
+        token,
+        encode_get_domain_separator().map_err(err_from!())?,
+    )
+    .await?;
+    if res.0.len() != 32 {
+        return Err(err_custom_create!(
+            "Invalid response from getDomainSeperator {:?}",
+            res
+        ));
+    };
+    Ok(H256::from_slice(&res.0))
+}
+
+pub async fn get_permit_nonce(
+    web3: &Web3<Http>,
+    token: Address,
+    owner: Address,
+) -> Result<U256, PaymentError> {
+    let res = call_contract_view(
+        web3,
+        token,
+        encode_erc20_permit_nonces(owner).map_err(err_from!())?,
+    )
+    .await?;
+    if res.0.len() != 32 {
+        return Err(err_custom_create!(
+            "Invalid response from ERC20 nonces {:?}",
+            res
+        ));
+    };
+    Ok(U256::from_big_endian(&res.0))
+}
+
+pub async fn get_permit_domain_separator(
+    web3: &Web3<Http>,
+    token: Address,
+) -> Result<H256, PaymentError> {
+    let res = call_contract_view(
+        web3,
+        token,
+        encode_erc20_domain_separator().map_err(err_from!())?,
+    )
+    .await?;
+    if res.0.len() != 32 {
+        return Err(err_custom_create!(
+            "Invalid response from ERC20 DOMAIN_SEPARATOR {:?}",
+            res
+        ));
+    };
+    Ok(H256::from_slice(&res.0))
+}
+
+/// Token is considered EIP-2612 compatible when both nonces and DOMAIN_SEPARATOR can be read
+pub async fn check_permit_support(
+    web3: &Web3<Http>,
+    token: Address,
+    owner: Address,
+) -> Result<bool, PaymentError> {
+    if let Err(err) = get_permit_nonce(web3, token, owner).await {
+        log::debug!("Token {:#x} does not support nonces: {}", token, err);
+        return Ok(false);
+    }
+    if let Err(err) = get_permit_domain_separator(web3, token).await {
+        log::debug!(
+            "Token {:#x} does not support DOMAIN_SEPARATOR: {}",
+            token,
+            err
+        );
+        return Ok(false);
+    }
+    Ok(true)
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -0,0 +1,78 @@
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use web3::types::U256;
+
+/// Capacity of the event channel, slow receivers get lagged error and miss oldest events
+pub const EVENT_CHANNEL_CAPACITY: usize = 1000;
+
+/// Events published by the payment engine, addresses are in lowercase hex format like in db
+#[derive(Debug, Clone, Serialize)]
+#[serde(
+    rename_all = "camelCase",
+    rename_all_fields = "camelCase",
+    tag = "type"
+)]
+pub enum PaymentEvent {
+    TransferQueued {
+        transfer_id: i64,
+        chain_id: i64,
+        from_addr: String,
+        receiver_addr: String,
+        token_addr: Option<String>,
+        amount: U256,
+    },
+    TxCreated {
+        tx_id: i64,
+        chain_id: i64,
+        method: String,
+        from_addr: String,
+        transfer_ids: Vec<i64>,
+    },
+    TxSigned {
+        tx_id: i64,
+        chain_id: i64,
+    },
+    TxBroadcast {
+        tx_id: i64,
+        chain_id: i64,
+        tx_hash: Option<String>,
+        broadcast_count: i64,
+    },
+    TxConfirmed {
+        tx_id: i64,
+        chain_id: i64,
+        tx_hash: Option<String>,
+        fee_paid: Option<String>,
+    },
+    TxFailed {
+        tx_id: i64,
+        chain_id: i64,
+        error: String,
+    },
+    AllowanceRequested {
+        chain_id: i64,
+        owner: String,
+        token_addr: String,
+        spender_addr: String,
+        amount: U256,
+    },
+    /// Balance does not cover transfers or expected gas
+    LowBalance {
+        chain_id: i64,
+        account: String,
+        /// None for gas currency
+        token_addr: Option<String>,
+        balance: U256,
+        required: U256,
+    },
+    /// Transfer was not sent before its deadline and was marked as failed
+    DeadlineExceeded {
+        transfer_id: i64,
+        chain_id: i64,
+        deadline: DateTime<Utc>,
+    },
+    ProviderError {
+        chain_id: i64,
+        error: String,
+    },
+}
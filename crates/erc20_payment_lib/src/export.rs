@@ -0,0 +1,155 @@
+use std::str::FromStr;
+
+use crate::db::model::TransferExportDao;
+use crate::db::ops::{get_transfers_for_export, TransferExportFilter};
+use crate::error::{CustomError, ErrorBag, PaymentError};
+use crate::setup::PaymentSetup;
+use crate::utils::u256_to_rust_dec;
+use crate::{err_custom_create, err_from};
+
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use sqlx::SqlitePool;
+use web3::types::{Address, U256};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ExportFormat {
+    Csv,
+    /// Semicolon separated CSV, opened correctly by spreadsheets using comma as decimal separator
+    CsvSemicolon,
+    Json,
+}
+
+impl FromStr for ExportFormat {
+    type Err = PaymentError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "csv" => Ok(ExportFormat::Csv),
+            "csv-semicolon" => Ok(ExportFormat::CsvSemicolon),
+            "json" => Ok(ExportFormat::Json),
+            _ => Err(err_custom_create!(
+                "Unknown export format {}, expected csv, csv-semicolon or json",
+                s
+            )),
+        }
+    }
+}
+
+impl ExportFormat {
+    pub fn content_type(&self) -> &'static str {
+        match self {
+            ExportFormat::Csv | ExportFormat::CsvSemicolon => "text/csv",
+            ExportFormat::Json => "application/json",
+        }
+    }
+}
+
+#[derive(Serialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct TransferExportRow {
+    pub transfer_id: i64,
+    pub payment_id: Option<String>,
+    pub chain_id: i64,
+    pub from_addr: String,
+    pub receiver_addr: String,
+    pub token_addr: Option<String>,
+    pub token_amount: String,
+    /// Amount in token (ether) units, None for tokens not found in config (decimals unknown)
+    pub amount: Option<String>,
+    pub fee_paid: Option<String>,
+    pub tx_id: i64,
+    pub tx_hash: Option<String>,
+    pub block_number: Option<i64>,
+    pub confirm_date: Option<DateTime<Utc>>,
+    pub tx_fee_paid: Option<String>,
+    pub tx_url: Option<String>,
+    pub receiver_url: Option<String>,
+}
+
+fn create_export_row(
+    payment_setup: &PaymentSetup,
+    transfer: TransferExportDao,
+) -> Result<TransferExportRow, PaymentError> {
+    let chain_setup = payment_setup.chain_setup.get(&transfer.chain_id);
+    let block_explorer_url = chain_setup
+        .and_then(|chain_setup| chain_setup.block_explorer_url.as_ref())
+        .map(|url| url.trim_end_matches('/'));
+    let token_addr = transfer
+        .token_addr
+        .as_ref()
+        .map(|addr| Address::from_str(addr))
+        .transpose()
+        .map_err(err_from!())?;
+    let amount = match chain_setup.and_then(|chain_setup| chain_setup.get_decimals(token_addr)) {
+        Some(decimals) => Some(
+            u256_to_rust_dec(
+                U256::from_dec_str(&transfer.token_amount).map_err(err_from!())?,
+                Some(decimals),
+            )
+            .map_err(err_from!())?
+            .to_string(),
+        ),
+        None => None,
+    };
+    Ok(TransferExportRow {
+        transfer_id: transfer.id,
+        payment_id: transfer.payment_id,
+        chain_id: transfer.chain_id,
+        tx_url: match (block_explorer_url, transfer.tx_hash.as_ref()) {
+            (Some(url), Some(tx_hash)) => Some(format!("{url}/tx/{tx_hash}")),
+            _ => None,
+        },
+        receiver_url: block_explorer_url
+            .map(|url| format!("{}/address/{}", url, transfer.receiver_addr)),
+        from_addr: transfer.from_addr,
+        receiver_addr: transfer.receiver_addr,
+        token_addr: transfer.token_addr,
+        token_amount: transfer.token_amount,
+        amount,
+        fee_paid: transfer.fee_paid,
+        tx_id: transfer.tx_id,
+        tx_hash: transfer.tx_hash,
+        block_number: transfer.block_number,
+        confirm_date: transfer.confirm_date,
+        tx_fee_paid: transfer.tx_fee_paid,
+    })
+}
+
+/// Completed transfers with their transactions in the given format
+pub async fn export_transfers(
+    conn: &SqlitePool,
+    payment_setup: &PaymentSetup,
+    filter: &TransferExportFilter,
+    format: ExportFormat,
+) -> Result<String, PaymentError> {
+    let rows = get_transfers_for_export(conn, filter)
+        .await
+        .map_err(err_from!())?
+        .into_iter()
+        .map(|transfer| create_export_row(payment_setup, transfer))
+        .collect::<Result<Vec<_>, _>>()?;
+    match format {
+        ExportFormat::Json => serde_json::to_string_pretty(&rows)
+            .map_err(|err| err_custom_create!("Failed to serialize export: {}", err)),
+        ExportFormat::Csv | ExportFormat::CsvSemicolon => {
+            let mut writer = csv::WriterBuilder::new()
+                .delimiter(if format == ExportFormat::Csv {
+                    b','
+                } else {
+                    b';'
+                })
+                .from_writer(Vec::new());
+            for row in rows {
+                writer
+                    .serialize(row)
+                    .map_err(|err| err_custom_create!("Failed to write CSV row: {}", err))?;
+            }
+            let content = writer
+                .into_inner()
+                .map_err(|err| err_custom_create!("Failed to write CSV: {}", err))?;
+            String::from_utf8(content)
+                .map_err(|err| err_custom_create!("Export is not valid UTF-8: {}", err))
+        }
+    }
+}
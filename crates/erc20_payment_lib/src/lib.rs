@@ -1,16 +1,21 @@
+pub mod balance;
 pub mod config;
 pub mod contracts;
 pub mod db;
+pub mod eip712;
 pub mod error;
 pub mod eth;
+pub mod events;
+pub mod export;
 pub mod misc;
 pub mod multi;
 pub mod runtime;
+pub mod screening;
 pub mod service;
 pub mod setup;
 pub mod transaction;
 pub mod utils;
 //@todo - add feature
-mod sender;
+pub mod sender;
 pub mod server;
 mod signer;
@@ -1,20 +1,30 @@
 use crate::db::create_sqlite_connection;
 use std::collections::BTreeMap;
 
-use crate::error::PaymentError;
+use crate::error::{CustomError, ErrorBag, PaymentError, PaymentRuntimeError};
+use crate::{err_custom_create, err_from};
 
-use crate::setup::PaymentSetup;
+use crate::setup::{ChainSetup, PaymentSetup};
 
 use crate::config;
 use secp256k1::SecretKey;
 use sqlx::SqlitePool;
 
+use crate::balance::{get_account_balances, AccountBalance};
 use crate::config::AdditionalOptions;
+use crate::db::model::{TokenTransferDao, APPROVAL_STATE_PENDING};
+use crate::db::ops::{
+    get_token_transfer, get_token_transfer_retry, get_transaction, insert_token_transfer,
+};
+use crate::eth::get_eth_addr_from_secret;
+use crate::events::{PaymentEvent, EVENT_CHANNEL_CAPACITY};
+use crate::screening::ReceiverScreening;
 use crate::sender::service_loop;
+use crate::transaction::create_token_transfer;
 use chrono::{DateTime, Utc};
 use serde::Serialize;
 use std::sync::Arc;
-use tokio::sync::Mutex;
+use tokio::sync::{broadcast, watch, Mutex};
 use tokio::task::JoinHandle;
 use web3::types::{Address, U256};
 
@@ -22,7 +32,6 @@ use web3::types::{Address, U256};
 pub struct SharedInfoTx {
     pub message: String,
     pub error: Option<String>,
-    pub skip: bool,
 }
 
 #[derive(Debug, Clone, Serialize)]
@@ -31,6 +40,15 @@ pub struct FaucetData {
     pub last_cleanup: DateTime<Utc>,
 }
 
+#[derive(Debug, Clone, Default, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct BatchGasInfo {
+    /// Average gas limit per receiver of recently estimated multi transfers
+    pub gas_per_receiver: Option<u64>,
+    /// Number of receivers allowed after failed estimation, grows back with successful ones
+    pub max_receivers: Option<usize>,
+}
+
 #[derive(Debug, Clone, Serialize)]
 pub struct SharedState {
     /// Additional engine info about processed transactions
@@ -38,9 +56,25 @@ pub struct SharedState {
     pub faucet: Option<FaucetData>,
     pub inserted: usize,
     pub idling: bool,
+    /// Gather all pending transfers right away, ignoring payout thresholds and batching policy
+    pub force_flush: bool,
+    /// Adaptive batch sizing info per chain
+    pub batch_gas: BTreeMap<i64, BatchGasInfo>,
+    /// Receiver screening list, reloadable at runtime
+    #[serde(skip)]
+    pub screening: Option<Arc<ReceiverScreening>>,
+    /// Engine does not pick up new work, transfers can still be added
+    pub paused: bool,
+    #[serde(skip)]
+    pub events: broadcast::Sender<PaymentEvent>,
 }
 
 impl SharedState {
+    pub fn emit(&self, event: PaymentEvent) {
+        log::debug!("Payment event: {:?}", event);
+        //error only means there are no subscribers
+        let _ = self.events.send(event);
+    }
     pub fn set_tx_message(&mut self, id: i64, message: String) {
         if let Some(info) = self.current_tx_info.get_mut(&id) {
             info.message = message;
@@ -50,7 +84,6 @@ impl SharedState {
                 SharedInfoTx {
                     message,
                     error: None,
-                    skip: false,
                 },
             );
         }
@@ -64,28 +97,84 @@ impl SharedState {
                 SharedInfoTx {
                     message: "".to_string(),
                     error,
-                    skip: false,
                 },
             );
         }
     }
-    pub fn skip_tx(&mut self, id: i64) -> bool {
-        if let Some(info) = self.current_tx_info.get_mut(&id) {
-            info.skip = true;
-            true
-        } else {
-            false
+    pub fn delete_tx_info(&mut self, id: i64) {
+        self.current_tx_info.remove(&id);
+    }
+    pub fn record_batch_gas(&mut self, chain_id: i64, gas_limit: u64, receivers: usize) {
+        if receivers == 0 {
+            return;
+        }
+        let info = self.batch_gas.entry(chain_id).or_default();
+        let sample = gas_limit / receivers as u64;
+        info.gas_per_receiver = Some(match info.gas_per_receiver {
+            Some(avg) => (avg * 3 + sample) / 4,
+            None => sample,
+        });
+        if let Some(max_receivers) = info.max_receivers {
+            if receivers >= max_receivers {
+                info.max_receivers = Some(max_receivers + max_receivers / 4 + 1);
+            }
         }
     }
-    pub fn is_skipped(&mut self, id: i64) -> bool {
-        if let Some(info) = self.current_tx_info.get_mut(&id) {
-            info.skip
-        } else {
-            false
+    pub fn record_batch_failed(&mut self, chain_id: i64, receivers: usize) {
+        let info = self.batch_gas.entry(chain_id).or_default();
+        info.max_receivers = Some(std::cmp::max(receivers / 2, 1));
+    }
+    pub fn get_max_batch_size(&self, chain_setup: &ChainSetup) -> usize {
+        let mut max_batch_size = chain_setup.multi_contract_max_at_once;
+        if let Some(info) = self.batch_gas.get(&chain_setup.chain_id) {
+            if let Some(max_receivers) = info.max_receivers {
+                max_batch_size = std::cmp::min(max_batch_size, max_receivers);
+            }
+            if let (Some(max_gas), Some(gas_per_receiver)) =
+                (chain_setup.multi_contract_max_gas, info.gas_per_receiver)
+            {
+                if let Some(receivers_by_gas) = max_gas.checked_div(gas_per_receiver) {
+                    max_batch_size = std::cmp::min(max_batch_size, receivers_by_gas as usize);
+                }
+            }
         }
+        std::cmp::max(max_batch_size, 1)
     }
-    pub fn delete_tx_info(&mut self, id: i64) {
-        self.current_tx_info.remove(&id);
+}
+
+/// Signal telling the engine to stop picking up new work,
+/// the step being processed is finished and persisted before exiting
+#[derive(Debug, Clone)]
+pub struct ShutdownToken {
+    receiver: watch::Receiver<bool>,
+}
+
+impl ShutdownToken {
+    pub fn new() -> (watch::Sender<bool>, Self) {
+        let (sender, receiver) = watch::channel(false);
+        (sender, ShutdownToken { receiver })
+    }
+
+    pub fn is_shutdown(&self) -> bool {
+        *self.receiver.borrow()
+    }
+
+    /// Resolves when shutdown is requested or the runtime is dropped
+    pub async fn wait(&self) {
+        let mut receiver = self.receiver.clone();
+        while !*receiver.borrow_and_update() {
+            if receiver.changed().await.is_err() {
+                return;
+            }
+        }
+    }
+
+    /// Sleep which is interrupted by shutdown, returns false when interrupted
+    pub async fn sleep(&self, duration: std::time::Duration) -> bool {
+        tokio::select! {
+            _ = tokio::time::sleep(duration) => true,
+            _ = self.wait() => false,
+        }
     }
 }
 
@@ -123,39 +212,220 @@ impl Default for ValidatedOptions {
         }
     }
 }
+/// Handle of the transfer added through PaymentRuntime
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TransferHandle {
+    pub id: i64,
+    pub chain_id: i64,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase", tag = "status")]
+pub enum TransferStatus {
+    PendingApproval,
+    /// Sender balance does not cover the transfer, it is batched once funds are added
+    WaitingForFunds,
+    Queued,
+    Processing {
+        tx_id: i64,
+    },
+    Confirmed {
+        tx_id: i64,
+        tx_hash: Option<String>,
+    },
+    /// Transfer failed and new attempt was created by retry policy
+    Retried {
+        retry_id: i64,
+        error: String,
+    },
+    Failed {
+        error: String,
+        dead_letter: bool,
+    },
+}
+
 pub struct PaymentRuntime {
     pub runtime_handle: JoinHandle<()>,
     pub setup: PaymentSetup,
     pub shared_state: Arc<Mutex<SharedState>>,
     pub conn: SqlitePool,
+    /// Receives engine events from the start, use subscribe_events for more receivers
+    pub events: broadcast::Receiver<PaymentEvent>,
+    shutdown_sender: watch::Sender<bool>,
 }
 
-/*
-async fn process_cli(
-    conn: &SqlitePool,
-    cli: &ValidatedOptions,
-    secret_key: &SecretKey,
-) -> Result<(), PaymentError> {
-    let from_addr = get_eth_addr_from_secret(secret_key);
-    for transaction_no in 0..cli.receivers.len() {
-        let receiver = cli.receivers[transaction_no];
-        let amount = cli.amounts[transaction_no];
-        let token_transfer = create_token_transfer(
-            from_addr,
-            receiver,
-            cli.chain_id as u64,
-            cli.token_addr,
-            amount,
-        );
-        let _token_transfer = insert_token_transfer(conn, &token_transfer)
+impl PaymentRuntime {
+    /// Ask the engine to stop and wait until the current step is persisted
+    pub async fn shutdown(self) -> Result<(), PaymentError> {
+        log::info!("Shutting down payment engine...");
+        //error means engine already finished and dropped its token
+        let _ = self.shutdown_sender.send(true);
+        self.runtime_handle
+            .await
+            .map_err(|err| err_custom_create!("Payment engine task failed: {}", err))?;
+        log::info!("Payment engine stopped");
+        Ok(())
+    }
+
+    /// Add transfer to the queue, it is sent by the engine with next gathering
+    pub async fn transfer(
+        &self,
+        chain_id: i64,
+        from: Address,
+        receiver: Address,
+        token_addr: Option<Address>,
+        amount: U256,
+        payment_id: Option<&str>,
+    ) -> Result<TransferHandle, PaymentRuntimeError> {
+        if !self.setup.chain_setup.contains_key(&chain_id) {
+            return Err(PaymentRuntimeError::ChainNotFound(chain_id));
+        }
+        if !self
+            .setup
+            .secret_keys
+            .iter()
+            .any(|sk| get_eth_addr_from_secret(sk) == from)
+        {
+            return Err(PaymentRuntimeError::SenderNotFound(from));
+        }
+        if let Some(screening) = self.shared_state.lock().await.screening.clone() {
+            screening
+                .check(receiver)
+                .map_err(|reason| PaymentRuntimeError::ReceiverRejected { receiver, reason })?;
+        }
+        let token_transfer = insert_token_transfer(
+            &self.conn,
+            &create_token_transfer(from, receiver, chain_id, payment_id, token_addr, amount),
+        )
+        .await
+        .map_err(err_from!())?;
+        self.shared_state
+            .lock()
             .await
-            .map_err(err_from!())?;
+            .emit(PaymentEvent::TransferQueued {
+                transfer_id: token_transfer.id,
+                chain_id,
+                from_addr: token_transfer.from_addr,
+                receiver_addr: token_transfer.receiver_addr,
+                token_addr: token_transfer.token_addr,
+                amount,
+            });
+        Ok(TransferHandle {
+            id: token_transfer.id,
+            chain_id,
+        })
+    }
+
+    pub async fn transfer_status(&self, id: i64) -> Result<TransferStatus, PaymentRuntimeError> {
+        let token_transfer = get_token_transfer(&self.conn, id)
+            .await
+            .map_err(err_from!())?
+            .ok_or(PaymentRuntimeError::TransferNotFound(id))?;
+        self.get_transfer_status(&token_transfer).await
+    }
+
+    async fn get_transfer_status(
+        &self,
+        token_transfer: &TokenTransferDao,
+    ) -> Result<TransferStatus, PaymentRuntimeError> {
+        if let Some(error) = &token_transfer.error {
+            if let Some(retry) = get_token_transfer_retry(&self.conn, token_transfer.id)
+                .await
+                .map_err(err_from!())?
+            {
+                return Ok(TransferStatus::Retried {
+                    retry_id: retry.id,
+                    error: error.clone(),
+                });
+            }
+            return Ok(TransferStatus::Failed {
+                error: error.clone(),
+                dead_letter: token_transfer.dead_letter,
+            });
+        }
+        match (token_transfer.tx_id, &token_transfer.fee_paid) {
+            (Some(tx_id), Some(_)) => {
+                let tx = get_transaction(&self.conn, tx_id)
+                    .await
+                    .map_err(err_from!())?;
+                Ok(TransferStatus::Confirmed {
+                    tx_id,
+                    tx_hash: tx.tx_hash,
+                })
+            }
+            (Some(tx_id), None) => Ok(TransferStatus::Processing { tx_id }),
+            (None, _) => {
+                if token_transfer.approval_state.as_deref() == Some(APPROVAL_STATE_PENDING) {
+                    Ok(TransferStatus::PendingApproval)
+                } else if token_transfer.waiting_for_funds {
+                    Ok(TransferStatus::WaitingForFunds)
+                } else {
+                    Ok(TransferStatus::Queued)
+                }
+            }
+        }
     }
-    Ok(())
 
-    //service_loop(&mut conn, &web3, &secret_key).await;
+    /// Wait until the transfer, or its last retry attempt, is confirmed.
+    /// Returns the confirmed status with id of the attempt which succeeded
+    pub async fn wait_for_confirmation(
+        &self,
+        id: i64,
+    ) -> Result<(i64, TransferStatus), PaymentRuntimeError> {
+        let mut id = id;
+        loop {
+            let status = self.transfer_status(id).await?;
+            match status {
+                TransferStatus::Confirmed { .. } => return Ok((id, status)),
+                TransferStatus::Retried { retry_id, .. } => {
+                    id = retry_id;
+                    continue;
+                }
+                //transfer not moved to dead letter yet is going to be retried
+                TransferStatus::Failed { error, dead_letter }
+                    if dead_letter || self.setup.retry_policy.is_none() =>
+                {
+                    return Err(PaymentRuntimeError::TransferFailed { id, error });
+                }
+                _ => {}
+            }
+            if self.runtime_handle.is_finished() {
+                return Err(PaymentRuntimeError::EngineStopped);
+            }
+            tokio::time::sleep(std::time::Duration::from_secs(std::cmp::max(
+                self.setup.process_sleep,
+                1,
+            )))
+            .await;
+        }
+    }
+
+    /// Balances of all senders on all configured chains
+    pub async fn get_balances(&self) -> Result<Vec<AccountBalance>, PaymentRuntimeError> {
+        Ok(get_account_balances(&self.conn, &self.setup).await?)
+    }
+
+    /// Stop picking up new transactions and gathering transfers,
+    /// transaction being processed is finished first
+    pub async fn pause(&self) {
+        log::info!("Pausing payment engine");
+        self.shared_state.lock().await.paused = true;
+    }
+
+    pub async fn resume(&self) {
+        log::info!("Resuming payment engine");
+        self.shared_state.lock().await.paused = false;
+    }
+
+    pub async fn is_paused(&self) -> bool {
+        self.shared_state.lock().await.paused
+    }
+
+    pub async fn subscribe_events(&self) -> broadcast::Receiver<PaymentEvent> {
+        self.shared_state.lock().await.events.subscribe()
+    }
 }
-*/
 
 pub async fn start_payment_engine(
     secret_keys: &[SecretKey],
@@ -186,24 +456,41 @@ pub async fn start_payment_engine(
         create_sqlite_connection(Some(db_filename), true).await?
     };
 
-    //process_cli(&mut conn, &cli, &payment_setup.secret_key).await?;
+    let screening = match payment_setup.screening.as_ref() {
+        Some(settings) => Some(Arc::new(
+            ReceiverScreening::load(&conn, settings, &payment_setup.receiver_accounts).await?,
+        )),
+        None => None,
+    };
 
     let ps = payment_setup.clone();
 
+    let (events_sender, events) = broadcast::channel(EVENT_CHANNEL_CAPACITY);
     let shared_state = Arc::new(Mutex::new(SharedState {
         inserted: 0,
         idling: false,
+        force_flush: options.force_flush,
+        batch_gas: BTreeMap::new(),
+        screening,
+        paused: false,
+        events: events_sender,
         current_tx_info: BTreeMap::new(),
         faucet: None,
     }));
     let shared_state_clone = shared_state.clone();
     let conn_ = conn.clone();
-    let jh = tokio::spawn(async move { service_loop(shared_state_clone, &conn_, &ps).await });
+    let (shutdown_sender, shutdown_token) = ShutdownToken::new();
+    let jh =
+        tokio::spawn(
+            async move { service_loop(shared_state_clone, &conn_, &ps, shutdown_token).await },
+        );
 
     Ok(PaymentRuntime {
         runtime_handle: jh,
         setup: payment_setup,
         shared_state,
         conn,
+        events,
+        shutdown_sender,
     })
 }
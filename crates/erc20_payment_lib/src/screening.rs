@@ -0,0 +1,138 @@
+use std::collections::HashSet;
+use std::fs;
+use std::str::FromStr;
+
+use crate::config::{ScreeningMode, ScreeningSettings};
+use crate::db::ops::get_all_screening_addresses;
+use crate::error::{CustomError, ErrorBag, PaymentError};
+use crate::{err_custom_create, err_from};
+
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use sqlx::SqlitePool;
+use web3::types::Address;
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ReceiverScreening {
+    pub mode: ScreeningMode,
+    #[serde(skip_serializing)]
+    pub addresses: HashSet<Address>,
+    pub loaded_date: DateTime<Utc>,
+}
+
+/// Parse screening list, one address per line or first column of CSV export.
+/// First line not looking like an address is treated as CSV header and skipped.
+pub fn parse_screening_list(content: &str) -> Result<Vec<Address>, PaymentError> {
+    let mut addresses = Vec::new();
+    let mut first_line = true;
+    for (line_no, line) in content.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let column = line.split(',').next().unwrap_or_default().trim();
+        match Address::from_str(column) {
+            Ok(address) => addresses.push(address),
+            Err(_err) if first_line && !column.starts_with("0x") => {
+                log::warn!(
+                    "Skipping screening list header, line {}: {}",
+                    line_no + 1,
+                    line
+                );
+            }
+            Err(_err) => {
+                //malformed address is an error, skipping it could let screened receiver through
+                return Err(err_custom_create!(
+                    "Cannot parse screening address, line {}",
+                    line_no + 1
+                ));
+            }
+        }
+        first_line = false;
+    }
+    Ok(addresses)
+}
+
+impl ReceiverScreening {
+    /// Load addresses from all sources given in settings
+    pub async fn load(
+        conn: &SqlitePool,
+        settings: &ScreeningSettings,
+        receiver_accounts: &[Address],
+    ) -> Result<Self, PaymentError> {
+        let mut addresses = settings.addresses.iter().cloned().collect::<HashSet<_>>();
+        if settings.mode == ScreeningMode::Allowlist {
+            addresses.extend(receiver_accounts.iter().cloned());
+        }
+        if let Some(file) = settings.file.as_ref() {
+            let content = fs::read_to_string(file).map_err(err_from!())?;
+            addresses.extend(parse_screening_list(&content)?);
+        }
+        if settings.database {
+            for screening_address in get_all_screening_addresses(conn)
+                .await
+                .map_err(err_from!())?
+            {
+                addresses
+                    .insert(Address::from_str(&screening_address.address).map_err(err_from!())?);
+            }
+        }
+        log::info!(
+            "Loaded {} addresses for receiver screening ({:?})",
+            addresses.len(),
+            settings.mode
+        );
+        Ok(ReceiverScreening {
+            mode: settings.mode,
+            addresses,
+            loaded_date: Utc::now(),
+        })
+    }
+
+    /// Returns error message when receiver should not be paid
+    pub fn check(&self, receiver: Address) -> Result<(), String> {
+        match self.mode {
+            ScreeningMode::Allowlist if !self.addresses.contains(&receiver) => Err(format!(
+                "Receiver {receiver:#x} rejected by screening: not on allowlist"
+            )),
+            ScreeningMode::Denylist if self.addresses.contains(&receiver) => Err(format!(
+                "Receiver {receiver:#x} rejected by screening: on denylist"
+            )),
+            _ => Ok(()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_screening_check() {
+        let addresses = parse_screening_list(
+            "# sanctions export\n0x0000000000000000000000000000000000000001,entity\n\n0x0000000000000000000000000000000000000002\n",
+        )
+        .unwrap();
+        assert_eq!(addresses.len(), 2);
+        let addresses_with_header = parse_screening_list(
+            "address,name\n0x0000000000000000000000000000000000000001,entity\n",
+        )
+        .unwrap();
+        assert_eq!(addresses_with_header, vec![Address::from_low_u64_be(1)]);
+        assert!(
+            parse_screening_list("0x0000000000000000000000000000000000000001\naddress,name\n")
+                .is_err()
+        );
+        let mut screening = ReceiverScreening {
+            mode: ScreeningMode::Denylist,
+            addresses: addresses.into_iter().collect(),
+            loaded_date: Utc::now(),
+        };
+        assert!(screening.check(Address::from_low_u64_be(1)).is_err());
+        assert!(screening.check(Address::from_low_u64_be(3)).is_ok());
+        screening.mode = ScreeningMode::Allowlist;
+        assert!(screening.check(Address::from_low_u64_be(1)).is_ok());
+        assert!(screening.check(Address::from_low_u64_be(3)).is_err());
+    }
+}
@@ -3,23 +3,34 @@ use std::str::FromStr;
 use crate::db::model::*;
 use crate::db::ops::*;
 use crate::error::{AllowanceRequest, ErrorBag, PaymentError};
-use crate::transaction::create_erc20_approve;
+use crate::transaction::{create_erc20_approve, create_erc20_permit};
 
-use crate::setup::PaymentSetup;
-use crate::{err_create, err_from};
+use crate::error::CustomError;
+use crate::setup::{AllowancePolicy, PaymentSetup};
+use crate::{err_create, err_custom_create, err_from};
 
 use sqlx::SqlitePool;
 
 use crate::error::TransactionFailedError;
-use crate::eth::{check_allowance, get_eth_addr_from_secret};
+use crate::eth::{check_allowance, check_permit_support, get_eth_addr_from_secret};
 use web3::types::{Address, U256};
 
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AllowanceResult {
+    /// Allowance is already sufficient, no transaction was created
+    AlreadyGranted,
+    /// Approve transaction was created, batching has to wait for its confirmation
+    ApproveCreated,
+    /// Permit transaction sent by relayer was created, batching has to wait for its confirmation
+    PermitCreated,
+}
+
 pub async fn process_allowance(
     conn: &SqlitePool,
     payment_setup: &PaymentSetup,
     allowance_request: &AllowanceRequest,
-) -> Result<u32, PaymentError> {
-    let minimum_allowance: U256 = U256::max_value() / U256::from(2);
+) -> Result<AllowanceResult, PaymentError> {
+    let minimum_allowance: U256 = allowance_request.minimum;
     let chain_setup = payment_setup.get_chain_setup(allowance_request.chain_id)?;
     let web3 = payment_setup.get_provider(allowance_request.chain_id)?;
     let max_fee_per_gas = chain_setup.max_fee_per_gas;
@@ -54,11 +65,12 @@ pub async fn process_allowance(
                 )
                 .await?;
                 log::info!("Allowance on chain: {}", allowance);
-                if allowance > minimum_allowance {
+                if allowance >= minimum_allowance {
                     log::debug!(
                         "Allowance found on chain, update db_allowance with id {}",
                         db_allowance.id
                     );
+                    db_allowance.allowance = allowance.to_string();
                     db_allowance.confirm_date = Some(chrono::Utc::now());
                     update_allowance(conn, db_allowance)
                         .await
@@ -76,7 +88,7 @@ pub async fn process_allowance(
                 Address::from_str(&allowance_request.spender_addr).map_err(err_from!())?,
             )
             .await?;
-            if allowance > minimum_allowance {
+            if allowance >= minimum_allowance {
                 log::info!("Allowance found on chain, add entry to db");
                 let db_allowance = AllowanceDao {
                     id: 0,
@@ -111,12 +123,19 @@ pub async fn process_allowance(
                 "Failed to find private key for address: {from_addr}"
             ))))?;
 
+        let token_addr = Address::from_str(&allowance_request.token_addr).map_err(err_from!())?;
+        //permit sent by the owner itself costs the same gas as approve, so use it only with relayer
+        let use_permit = match chain_setup.meta_transaction_relayer {
+            Some(_) => check_permit_support(web3, token_addr, from_addr).await?,
+            None => false,
+        };
+
         let mut allowance = AllowanceDao {
             id: 0,
             owner: allowance_request.owner.clone(),
             token_addr: allowance_request.token_addr.clone(),
             spender: allowance_request.spender_addr.clone(),
-            allowance: U256::max_value().to_string(),
+            allowance: allowance_request.amount.to_string(),
             chain_id: allowance_request.chain_id,
             tx_id: None,
             fee_paid: None,
@@ -124,15 +143,37 @@ pub async fn process_allowance(
             error: None,
         };
 
-        let approve_tx = create_erc20_approve(
-            Address::from_str(&allowance_request.owner).map_err(err_from!())?,
-            Address::from_str(&allowance_request.token_addr).map_err(err_from!())?,
-            Address::from_str(&allowance_request.spender_addr).map_err(err_from!())?,
-            allowance_request.chain_id as u64,
-            None,
-            max_fee_per_gas,
-            priority_fee,
-        )?;
+        let approve_tx =
+            if let (true, Some(sender)) = (use_permit, chain_setup.meta_transaction_relayer) {
+                //allowance is confirmed on chain after the permit is processed, the same as approve
+                log::info!(
+                    "Token {:#x} supports permit, create permit tx sent by relayer {:#x}",
+                    token_addr,
+                    sender
+                );
+                create_erc20_permit(
+                    from_addr,
+                    sender,
+                    token_addr,
+                    Address::from_str(&allowance_request.spender_addr).map_err(err_from!())?,
+                    allowance_request.amount,
+                    allowance_request.chain_id as u64,
+                    None,
+                    max_fee_per_gas,
+                    priority_fee,
+                )?
+            } else {
+                create_erc20_approve(
+                    from_addr,
+                    token_addr,
+                    Address::from_str(&allowance_request.spender_addr).map_err(err_from!())?,
+                    allowance_request.amount,
+                    allowance_request.chain_id as u64,
+                    None,
+                    max_fee_per_gas,
+                    priority_fee,
+                )?
+            };
         let mut db_transaction = conn.begin().await.map_err(err_from!())?;
         let web3_tx_dao = insert_tx(&mut db_transaction, &approve_tx)
             .await
@@ -144,7 +185,174 @@ pub async fn process_allowance(
 
         db_transaction.commit().await.map_err(err_from!())?;
 
-        return Ok(1);
+        return Ok(if use_permit {
+            AllowanceResult::PermitCreated
+        } else {
+            AllowanceResult::ApproveCreated
+        });
     }
-    Ok(0)
+    Ok(AllowanceResult::AlreadyGranted)
+}
+
+/// Check if allowance cached in db is sufficient for batch of given amount according
+/// to the chain allowance policy. Returns allowance request when new approval is needed.
+pub async fn verify_allowance(
+    conn: &SqlitePool,
+    payment_setup: &PaymentSetup,
+    owner: &str,
+    token_addr: &str,
+    spender_addr: &str,
+    chain_id: i64,
+    batch_amount: U256,
+) -> Result<Option<AllowanceRequest>, PaymentError> {
+    let chain_setup = payment_setup.get_chain_setup(chain_id)?;
+    let (minimum, amount) = match &chain_setup.allowance_policy {
+        //this is some arbitrary number.
+        AllowancePolicy::Unlimited => (U256::max_value() / U256::from(2), U256::max_value()),
+        AllowancePolicy::ExactPerBatch => (batch_amount, batch_amount),
+        AllowancePolicy::Cap(cap) => {
+            if batch_amount > *cap {
+                return Err(err_custom_create!(
+                    "Batch amount {} exceeds allowance cap {}",
+                    batch_amount,
+                    cap
+                ));
+            }
+            (batch_amount, *cap)
+        }
+    };
+
+    let db_allowance = find_allowance(conn, owner, token_addr, spender_addr, chain_id)
+        .await
+        .map_err(err_from!())?;
+
+    let allowance_met = match db_allowance {
+        Some(mut db_allowance) => match db_allowance.confirm_date {
+            Some(confirm_date) => {
+                let recheck = match chain_setup.allowance_policy {
+                    AllowancePolicy::Unlimited => {
+                        chrono::Utc::now() - confirm_date
+                            > chrono::Duration::seconds(
+                                chain_setup.allowance_recheck_interval as i64,
+                            )
+                    }
+                    //limited allowance is consumed by transfers, so cache is always outdated
+                    _ => true,
+                };
+                let allowance = if recheck {
+                    log::debug!("Verifying cached allowance {} on chain", db_allowance.id);
+                    let allowance = check_allowance(
+                        payment_setup.get_provider(chain_id)?,
+                        Address::from_str(owner).map_err(err_from!())?,
+                        Address::from_str(token_addr).map_err(err_from!())?,
+                        Address::from_str(spender_addr).map_err(err_from!())?,
+                    )
+                    .await?;
+                    db_allowance.allowance = allowance.to_string();
+                    db_allowance.confirm_date = Some(chrono::Utc::now());
+                    update_allowance(conn, &db_allowance)
+                        .await
+                        .map_err(err_from!())?;
+                    allowance
+                } else {
+                    U256::from_dec_str(&db_allowance.allowance).map_err(err_from!())?
+                };
+                if allowance < minimum {
+                    log::debug!("Allowance already confirmed, but it is too small");
+                    false
+                } else {
+                    log::debug!("Allowance confirmed");
+                    true
+                }
+            }
+            None => {
+                log::debug!("Allowance request found, but not confirmed");
+                false
+            }
+        },
+        None => {
+            log::debug!("Allowance not found in db");
+            false
+        }
+    };
+    if allowance_met {
+        return Ok(None);
+    }
+    Ok(Some(AllowanceRequest {
+        owner: owner.to_string(),
+        token_addr: token_addr.to_string(),
+        spender_addr: spender_addr.to_string(),
+        chain_id,
+        amount,
+        minimum,
+    }))
+}
+
+/// Create transaction approving zero tokens. Token defaults to chain token
+/// and spender defaults to chain multi contract.
+pub async fn revoke_allowance(
+    conn: &SqlitePool,
+    payment_setup: &PaymentSetup,
+    owner: Address,
+    chain_id: i64,
+    token_addr: Option<Address>,
+    spender_addr: Option<Address>,
+) -> Result<AllowanceDao, PaymentError> {
+    let chain_setup = payment_setup.get_chain_setup(chain_id)?;
+    if !payment_setup
+        .secret_keys
+        .iter()
+        .any(|sk| get_eth_addr_from_secret(sk) == owner)
+    {
+        return Err(err_custom_create!(
+            "Failed to find private key for address: {owner:#x}"
+        ));
+    }
+    let token_addr = token_addr
+        .or(chain_setup.glm_address)
+        .ok_or_else(|| err_custom_create!("No token address for chain {}", chain_id))?;
+    let spender_addr = spender_addr
+        .or(chain_setup.multi_contract_address)
+        .ok_or_else(|| err_custom_create!("No spender address for chain {}", chain_id))?;
+
+    let approve_tx = create_erc20_approve(
+        owner,
+        token_addr,
+        spender_addr,
+        U256::zero(),
+        chain_id as u64,
+        None,
+        chain_setup.max_fee_per_gas,
+        chain_setup.priority_fee,
+    )?;
+    let mut db_transaction = conn.begin().await.map_err(err_from!())?;
+    let web3_tx_dao = insert_tx(&mut db_transaction, &approve_tx)
+        .await
+        .map_err(err_from!())?;
+    let allowance = insert_allowance(
+        &mut db_transaction,
+        &AllowanceDao {
+            id: 0,
+            owner: format!("{owner:#x}"),
+            token_addr: format!("{token_addr:#x}"),
+            spender: format!("{spender_addr:#x}"),
+            allowance: U256::zero().to_string(),
+            chain_id,
+            tx_id: Some(web3_tx_dao.id),
+            fee_paid: None,
+            confirm_date: None,
+            error: None,
+        },
+    )
+    .await
+    .map_err(err_from!())?;
+    db_transaction.commit().await.map_err(err_from!())?;
+    log::info!(
+        "Created allowance revoke tx {} for owner {:#x}, token {:#x}, spender {:#x}",
+        web3_tx_dao.id,
+        owner,
+        token_addr,
+        spender_addr
+    );
+    Ok(allowance)
 }
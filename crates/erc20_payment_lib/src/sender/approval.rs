@@ -0,0 +1,242 @@
+use std::str::FromStr;
+
+use crate::db::model::*;
+use crate::db::ops::*;
+use crate::error::{CustomError, ErrorBag, PaymentError};
+use crate::setup::{ApprovalRules, PaymentSetup};
+use crate::utils::rust_dec_to_u256;
+use crate::{err_custom_create, err_from};
+
+use chrono::Utc;
+use serde::Serialize;
+use sqlx::SqlitePool;
+use web3::types::{Address, U256};
+
+#[derive(Serialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct TransferPendingApproval {
+    pub transfer: TokenTransferDao,
+    pub approvals: Vec<TransferApprovalDao>,
+    pub required_approvers: usize,
+}
+
+/// Name of the first approval rule matched by the transfer,
+/// decimals are None for tokens not found in config
+pub async fn check_approval_rules(
+    conn: &SqlitePool,
+    approval_rules: &ApprovalRules,
+    decimals: Option<u32>,
+    token_transfer: &TokenTransferDao,
+) -> Result<Option<&'static str>, PaymentError> {
+    if let Some(amount_above) = approval_rules.amount_above {
+        let decimals = match decimals {
+            Some(decimals) => decimals,
+            //amount cannot be compared with the threshold, so leave it to the operator
+            None => return Ok(Some("amount of token with unknown decimals")),
+        };
+        let amount = U256::from_dec_str(&token_transfer.token_amount).map_err(err_from!())?;
+        if amount > rust_dec_to_u256(amount_above, Some(decimals)).map_err(err_from!())? {
+            return Ok(Some("amount above threshold"));
+        }
+    }
+    if let Some(token_addr) = token_transfer.token_addr.as_ref() {
+        let token_addr = Address::from_str(token_addr).map_err(err_from!())?;
+        if approval_rules.tokens.contains(&token_addr) {
+            return Ok(Some("token requires approval"));
+        }
+    }
+    if approval_rules.new_receiver
+        && !has_transfers_to_receiver(
+            conn,
+            &token_transfer.from_addr,
+            token_transfer.chain_id,
+            &token_transfer.receiver_addr,
+        )
+        .await
+        .map_err(err_from!())?
+    {
+        return Ok(Some("new receiver"));
+    }
+    Ok(None)
+}
+
+fn get_required_approvers(payment_setup: &PaymentSetup, chain_id: i64) -> usize {
+    payment_setup
+        .chain_setup
+        .get(&chain_id)
+        .and_then(|chain_setup| chain_setup.approval_rules.as_ref())
+        .map(|approval_rules| approval_rules.required_approvers)
+        .unwrap_or(1)
+}
+
+pub async fn get_transfers_pending_approval(
+    conn: &SqlitePool,
+    payment_setup: &PaymentSetup,
+) -> Result<Vec<TransferPendingApproval>, PaymentError> {
+    let mut res = Vec::new();
+    for transfer in get_token_transfers_pending_approval(conn)
+        .await
+        .map_err(err_from!())?
+    {
+        let approvals = get_transfer_approvals(conn, transfer.id)
+            .await
+            .map_err(err_from!())?;
+        res.push(TransferPendingApproval {
+            required_approvers: get_required_approvers(payment_setup, transfer.chain_id),
+            transfer,
+            approvals,
+        });
+    }
+    Ok(res)
+}
+
+/// Approver has to be on the allowlist of transfer chain and present its token
+fn verify_approver(
+    payment_setup: &PaymentSetup,
+    chain_id: i64,
+    approver: &str,
+    approver_token: &str,
+) -> Result<(), PaymentError> {
+    let approval_rules = payment_setup
+        .chain_setup
+        .get(&chain_id)
+        .and_then(|chain_setup| chain_setup.approval_rules.as_ref())
+        .ok_or_else(|| err_custom_create!("No approvers configured for chain {}", chain_id))?;
+    if !approval_rules.approvers.iter().any(|a| a == approver) {
+        return Err(err_custom_create!(
+            "{} is not allowed to approve transfers on chain {}",
+            approver,
+            chain_id
+        ));
+    }
+    match approval_rules.approver_tokens.get(approver) {
+        Some(token) if !approver_token.is_empty() && token == approver_token => Ok(()),
+        _ => Err(err_custom_create!("Invalid token of approver {}", approver)),
+    }
+}
+
+async fn record_decision(
+    conn: &SqlitePool,
+    payment_setup: &PaymentSetup,
+    transfer_id: i64,
+    approver: &str,
+    approver_token: &str,
+    decision: &str,
+    reason: Option<&str>,
+) -> Result<TokenTransferDao, PaymentError> {
+    if approver.is_empty() {
+        return Err(err_custom_create!("Approver identity has to be given"));
+    }
+    let token_transfer = get_token_transfer(conn, transfer_id)
+        .await
+        .map_err(err_from!())?
+        .ok_or_else(|| err_custom_create!("Transfer {} not found", transfer_id))?;
+    verify_approver(
+        payment_setup,
+        token_transfer.chain_id,
+        approver,
+        approver_token,
+    )?;
+    if token_transfer.approval_state.as_deref() != Some(APPROVAL_STATE_PENDING)
+        || token_transfer.error.is_some()
+    {
+        return Err(err_custom_create!(
+            "Transfer {} is not pending approval",
+            transfer_id
+        ));
+    }
+    let approvals = get_transfer_approvals(conn, transfer_id)
+        .await
+        .map_err(err_from!())?;
+    if approvals.iter().any(|a| a.approver == approver) {
+        return Err(err_custom_create!(
+            "Approver {} already decided on transfer {}",
+            approver,
+            transfer_id
+        ));
+    }
+    insert_transfer_approval(
+        conn,
+        &TransferApprovalDao {
+            id: 0,
+            transfer_id,
+            approver: approver.to_string(),
+            decision: decision.to_string(),
+            reason: reason.map(|r| r.to_string()),
+            created_date: Utc::now(),
+        },
+    )
+    .await
+    .map_err(err_from!())?;
+    Ok(token_transfer)
+}
+
+/// Record approval, transfer is released when enough distinct approvers approved it
+pub async fn approve_transfer(
+    conn: &SqlitePool,
+    payment_setup: &PaymentSetup,
+    transfer_id: i64,
+    approver: &str,
+    approver_token: &str,
+    reason: Option<&str>,
+) -> Result<TokenTransferDao, PaymentError> {
+    let mut token_transfer = record_decision(
+        conn,
+        payment_setup,
+        transfer_id,
+        approver,
+        approver_token,
+        APPROVAL_DECISION_APPROVE,
+        reason,
+    )
+    .await?;
+    let approved_count = get_transfer_approvals(conn, transfer_id)
+        .await
+        .map_err(err_from!())?
+        .iter()
+        .filter(|a| a.decision == APPROVAL_DECISION_APPROVE)
+        .count();
+    let required_approvers = get_required_approvers(payment_setup, token_transfer.chain_id);
+    log::info!(
+        "Transfer {} approved by {} ({} of {})",
+        transfer_id,
+        approver,
+        approved_count,
+        required_approvers
+    );
+    if approved_count >= required_approvers {
+        token_transfer.approval_state = Some(APPROVAL_STATE_APPROVED.to_string());
+        update_token_transfer(conn, &token_transfer)
+            .await
+            .map_err(err_from!())?;
+    }
+    Ok(token_transfer)
+}
+
+/// Record rejection, single rejection fails the transfer
+pub async fn reject_transfer(
+    conn: &SqlitePool,
+    payment_setup: &PaymentSetup,
+    transfer_id: i64,
+    approver: &str,
+    approver_token: &str,
+    reason: Option<&str>,
+) -> Result<TokenTransferDao, PaymentError> {
+    let mut token_transfer = record_decision(
+        conn,
+        payment_setup,
+        transfer_id,
+        approver,
+        approver_token,
+        APPROVAL_DECISION_REJECT,
+        reason,
+    )
+    .await?;
+    log::info!("Transfer {} rejected by {}", transfer_id, approver);
+    token_transfer.approval_state = Some(APPROVAL_STATE_REJECTED.to_string());
+    token_transfer.error = Some(format!("Rejected by {approver}"));
+    update_token_transfer(conn, &token_transfer)
+        .await
+        .map_err(err_from!())?;
+    Ok(token_transfer)
+}
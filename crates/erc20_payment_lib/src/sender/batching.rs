@@ -3,15 +3,24 @@ use std::str::FromStr;
 
 use crate::db::model::*;
 use crate::db::ops::*;
-use crate::error::{AllowanceRequest, ErrorBag, PaymentError};
+use crate::error::{ErrorBag, PaymentError};
+use crate::sender::{check_approval_rules, verify_allowance, GATHER_ERROR_PREFIX};
 
-use crate::transaction::{create_erc20_transfer, create_erc20_transfer_multi, create_eth_transfer};
+use crate::transaction::{
+    create_erc20_transfer, create_erc20_transfer_meta, create_erc20_transfer_multi,
+    create_eth_transfer, create_eth_transfer_multi,
+};
 
 use crate::error::CustomError;
-use crate::setup::PaymentSetup;
+use crate::eth::get_balance;
+use crate::events::PaymentEvent;
+use crate::runtime::SharedState;
+use crate::setup::{PaymentSetup, SpendingLimitsSetup};
 use crate::{err_create, err_custom_create, err_from};
 
 use sqlx::SqlitePool;
+use std::sync::Arc;
+use tokio::sync::Mutex;
 
 use web3::types::{Address, U256};
 
@@ -21,6 +30,8 @@ pub struct TokenTransferKey {
     pub receiver_addr: String,
     pub chain_id: i64,
     pub token_addr: Option<String>,
+    /// Set for transfers which are not merged with others to the same receiver
+    pub transfer_id: Option<i64>,
 }
 
 #[derive(Eq, Hash, PartialEq, Debug, Clone)]
@@ -38,12 +49,51 @@ pub struct TokenTransferMultiOrder {
     token_transfers: Vec<TokenTransferDao>,
 }
 
+/// Mark not sent transfers past their deadline as failed, held and not approved ones too
+pub async fn fail_transfers_past_deadline(
+    shared_state: Arc<Mutex<SharedState>>,
+    conn: &SqlitePool,
+) -> Result<(), PaymentError> {
+    for mut token_transfer in get_token_transfers_past_deadline(conn)
+        .await
+        .map_err(err_from!())?
+    {
+        let deadline = match token_transfer.deadline {
+            Some(deadline) => deadline,
+            None => continue,
+        };
+        log::warn!(
+            "Transfer {} missed its deadline {}, marking as failed",
+            token_transfer.id,
+            deadline
+        );
+        token_transfer.error = Some(TRANSFER_ERROR_DEADLINE_EXCEEDED.to_string());
+        update_token_transfer(conn, &token_transfer)
+            .await
+            .map_err(err_from!())?;
+        shared_state
+            .lock()
+            .await
+            .emit(PaymentEvent::DeadlineExceeded {
+                transfer_id: token_transfer.id,
+                chain_id: token_transfer.chain_id,
+                deadline,
+            });
+    }
+    Ok(())
+}
+
 pub async fn gather_transactions_pre(
+    shared_state: Arc<Mutex<SharedState>>,
     conn: &SqlitePool,
-    _payment_setup: &PaymentSetup,
+    payment_setup: &PaymentSetup,
+    force_flush: bool,
 ) -> Result<TokenTransferMap, PaymentError> {
     let mut transfer_map = TokenTransferMap::new();
 
+    fail_transfers_past_deadline(shared_state.clone(), conn).await?;
+
+    let screening = shared_state.lock().await.screening.clone();
     let mut token_transfers = get_pending_token_transfers(conn)
         .await
         .map_err(err_from!())?;
@@ -77,6 +127,14 @@ pub async fn gather_transactions_pre(
                     update_token_transfer(conn, f).await.map_err(err_from!())?;
                     continue;
                 }
+                if let Some(screening) = screening.as_ref() {
+                    if let Err(reason) = screening.check(rec_address) {
+                        log::warn!("Transfer {}: {}", f.id, reason);
+                        f.error = Some(reason);
+                        update_token_transfer(conn, f).await.map_err(err_from!())?;
+                        continue;
+                    }
+                }
             }
             Err(_err) => {
                 f.error = Some("Invalid receiver address".to_string());
@@ -86,11 +144,18 @@ pub async fn gather_transactions_pre(
         }
 
         //group transactions
+        let no_merge = f.no_merge
+            || payment_setup
+                .chain_setup
+                .get(&f.chain_id)
+                .map(|chain_setup| chain_setup.no_merge)
+                .unwrap_or(false);
         let key = TokenTransferKey {
             from_addr: f.from_addr.clone(),
             receiver_addr: f.receiver_addr.clone(),
             chain_id: f.chain_id,
             token_addr: f.token_addr.clone(),
+            transfer_id: if no_merge { Some(f.id) } else { None },
         };
         match transfer_map.get_mut(&key) {
             Some(v) => {
@@ -101,10 +166,563 @@ pub async fn gather_transactions_pre(
             }
         }
     }
+    apply_approval_rules(conn, payment_setup, &mut transfer_map).await?;
+    apply_spending_limits(conn, payment_setup, &mut transfer_map).await?;
+    if !force_flush {
+        apply_min_payout(payment_setup, &mut transfer_map)?;
+        apply_batching_policy(payment_setup, &mut transfer_map).await;
+    }
+    let waiting_for_funds = apply_funds_check(conn, payment_setup, &mut transfer_map).await?;
+    let shared_state = shared_state.lock().await;
+    for waiting in waiting_for_funds {
+        shared_state.emit(PaymentEvent::LowBalance {
+            chain_id: waiting.chain_id,
+            account: waiting.from_addr,
+            token_addr: waiting.token_addr,
+            balance: waiting.balance,
+            required: waiting.required,
+        });
+    }
     Ok(transfer_map)
 }
 
+/// Hold transfers matching chain approval rules until approved by operators
+async fn apply_approval_rules(
+    conn: &SqlitePool,
+    payment_setup: &PaymentSetup,
+    transfer_map: &mut TokenTransferMap,
+) -> Result<(), PaymentError> {
+    let keys = transfer_map.keys().cloned().collect::<Vec<_>>();
+    for key in keys {
+        let chain_setup = payment_setup.get_chain_setup(key.chain_id)?;
+        let approval_rules = match chain_setup.approval_rules.as_ref() {
+            Some(approval_rules) => approval_rules,
+            None => continue,
+        };
+        let decimals = chain_setup.get_decimals(
+            key.token_addr
+                .as_ref()
+                .map(|addr| Address::from_str(addr))
+                .transpose()
+                .map_err(err_from!())?,
+        );
+        let token_transfers = transfer_map.remove(&key).unwrap_or_default();
+        let mut accepted = Vec::with_capacity(token_transfers.len());
+        for mut token_transfer in token_transfers {
+            if token_transfer.approval_state.as_deref() != Some(APPROVAL_STATE_APPROVED) {
+                if let Some(rule) =
+                    check_approval_rules(conn, approval_rules, decimals, &token_transfer).await?
+                {
+                    log::warn!(
+                        "Transfer {} to {} matched approval rule: {}, approval required",
+                        token_transfer.id,
+                        token_transfer.receiver_addr,
+                        rule
+                    );
+                    token_transfer.approval_state = Some(APPROVAL_STATE_PENDING.to_string());
+                    update_token_transfer(conn, &token_transfer)
+                        .await
+                        .map_err(err_from!())?;
+                    continue;
+                }
+            }
+            accepted.push(token_transfer);
+        }
+        if !accepted.is_empty() {
+            transfer_map.insert(key, accepted);
+        }
+    }
+    Ok(())
+}
+
+/// Sum of amounts sent during last 24 hours according to transfer history
+async fn get_sent_last_day(
+    conn: &SqlitePool,
+    key: &TokenTransferKey,
+    receiver_addr: Option<&str>,
+) -> Result<U256, PaymentError> {
+    let mut sum = U256::zero();
+    for amount in get_sent_token_amounts_since(
+        conn,
+        &key.from_addr,
+        key.chain_id,
+        key.token_addr.as_deref(),
+        receiver_addr,
+        chrono::Utc::now() - chrono::Duration::days(1),
+    )
+    .await
+    .map_err(err_from!())?
+    {
+        sum += U256::from_dec_str(&amount).map_err(err_from!())?;
+    }
+    Ok(sum)
+}
+
+/// Name of the first limit breached by adding amount to the totals, limits equal to the total are not breached
+fn get_breached_limit(
+    limits: &SpendingLimitsSetup,
+    amount: U256,
+    receiver_transaction_total: U256,
+    receiver_total: U256,
+    sender_total: U256,
+) -> Option<&'static str> {
+    if limits.max_per_transfer.map(|l| amount > l) == Some(true) {
+        Some("max per transfer")
+    } else if limits
+        .max_per_receiver_per_transaction
+        .map(|l| receiver_transaction_total + amount > l)
+        == Some(true)
+    {
+        Some("max per receiver per transaction")
+    } else if limits
+        .max_per_receiver_daily
+        .map(|l| receiver_total + amount > l)
+        == Some(true)
+    {
+        Some("max per receiver daily")
+    } else if limits
+        .max_per_sender_daily
+        .map(|l| sender_total + amount > l)
+        == Some(true)
+    {
+        Some("max per sender daily")
+    } else {
+        None
+    }
+}
+
+/// Hold transfers breaching spending limits until they are approved,
+/// approved transfers are not checked, but count towards daily totals
+async fn apply_spending_limits(
+    conn: &SqlitePool,
+    payment_setup: &PaymentSetup,
+    transfer_map: &mut TokenTransferMap,
+) -> Result<(), PaymentError> {
+    let mut sorted_keys = Vec::new();
+    for (key, token_transfers) in transfer_map.iter() {
+        let chain_setup = payment_setup.get_chain_setup(key.chain_id)?;
+        let token_addr = key
+            .token_addr
+            .as_ref()
+            .map(|addr| Address::from_str(addr))
+            .transpose()
+            .map_err(err_from!())?;
+        if let Some(limits) = chain_setup.get_spending_limits(token_addr) {
+            let min_id = token_transfers
+                .iter()
+                .map(|t| t.id)
+                .min()
+                .unwrap_or_default();
+            sorted_keys.push((min_id, key.clone(), limits.clone()));
+        }
+    }
+    sorted_keys.sort_by_key(|(min_id, _, _)| *min_id);
+
+    let mut sender_totals = HashMap::<TokenTransferMultiKey, U256>::new();
+    let mut receiver_totals = HashMap::<TokenTransferKey, U256>::new();
+    for (_, key, limits) in sorted_keys {
+        let sender_key = TokenTransferMultiKey {
+            from_addr: key.from_addr.clone(),
+            chain_id: key.chain_id,
+            token_addr: key.token_addr.clone(),
+        };
+        let receiver_key = TokenTransferKey {
+            transfer_id: None,
+            ..key.clone()
+        };
+        let mut sender_total = match sender_totals.get(&sender_key) {
+            Some(total) => *total,
+            None if limits.max_per_sender_daily.is_some() => {
+                get_sent_last_day(conn, &key, None).await?
+            }
+            None => U256::zero(),
+        };
+        let mut receiver_total = match receiver_totals.get(&receiver_key) {
+            Some(total) => *total,
+            None if limits.max_per_receiver_daily.is_some() => {
+                get_sent_last_day(conn, &key, Some(&key.receiver_addr)).await?
+            }
+            None => U256::zero(),
+        };
+        let mut receiver_transaction_total = U256::zero();
+
+        let token_transfers = transfer_map.remove(&key).unwrap_or_default();
+        let mut accepted = Vec::with_capacity(token_transfers.len());
+        for mut token_transfer in token_transfers {
+            let amount = U256::from_dec_str(&token_transfer.token_amount).map_err(err_from!())?;
+            let breached =
+                if token_transfer.approval_state.as_deref() == Some(APPROVAL_STATE_APPROVED) {
+                    None
+                } else {
+                    get_breached_limit(
+                        &limits,
+                        amount,
+                        receiver_transaction_total,
+                        receiver_total,
+                        sender_total,
+                    )
+                };
+            if let Some(limit_name) = breached {
+                log::warn!(
+                    "Transfer {} of {} to {} breaches {} limit, approval required",
+                    token_transfer.id,
+                    amount,
+                    token_transfer.receiver_addr,
+                    limit_name
+                );
+                token_transfer.approval_state = Some(APPROVAL_STATE_PENDING.to_string());
+                update_token_transfer(conn, &token_transfer)
+                    .await
+                    .map_err(err_from!())?;
+                continue;
+            }
+            receiver_transaction_total += amount;
+            receiver_total += amount;
+            sender_total += amount;
+            accepted.push(token_transfer);
+        }
+        sender_totals.insert(sender_key, sender_total);
+        receiver_totals.insert(receiver_key, receiver_total);
+        if !accepted.is_empty() {
+            transfer_map.insert(key, accepted);
+        }
+    }
+    Ok(())
+}
+
+/// Split orders into batches of at most max_count receivers and max_amount in total,
+/// returns end index of every batch
+fn get_batch_boundaries(
+    orders: &[TokenTransferMultiOrder],
+    max_count: usize,
+    max_amount: Option<U256>,
+) -> Result<Vec<usize>, PaymentError> {
+    let mut boundaries = Vec::new();
+    let mut count = 0;
+    let mut amount = U256::zero();
+    for (idx, order) in orders.iter().enumerate() {
+        let mut order_amount = U256::zero();
+        for token_transfer in &order.token_transfers {
+            order_amount +=
+                U256::from_dec_str(&token_transfer.token_amount).map_err(err_from!())?;
+        }
+        let amount_exceeded = max_amount
+            .map(|max_amount| amount + order_amount > max_amount)
+            .unwrap_or(false);
+        if count > 0 && (count >= max_count || amount_exceeded) {
+            boundaries.push(idx);
+            count = 0;
+            amount = U256::zero();
+        }
+        count += 1;
+        amount += order_amount;
+    }
+    if count > 0 {
+        boundaries.push(orders.len());
+    }
+    Ok(boundaries)
+}
+
+/// Sender account without balance to cover its queued transfers
+struct WaitingForFunds {
+    from_addr: String,
+    chain_id: i64,
+    token_addr: Option<String>,
+    balance: U256,
+    /// Balance needed to cover reserved and all queued transfers
+    required: U256,
+}
+
+/// Remove transfers not covered by sender balance reduced by amounts reserved
+/// for not finished transactions, oldest transfers are covered first.
+/// Transfers left out are marked as waiting for funds until they are covered
+async fn apply_funds_check(
+    conn: &SqlitePool,
+    payment_setup: &PaymentSetup,
+    transfer_map: &mut TokenTransferMap,
+) -> Result<Vec<WaitingForFunds>, PaymentError> {
+    let mut groups = HashMap::<TokenTransferMultiKey, Vec<(i64, TokenTransferKey)>>::new();
+    for (key, token_transfers) in transfer_map.iter() {
+        let min_id = token_transfers
+            .iter()
+            .map(|t| t.id)
+            .min()
+            .unwrap_or_default();
+        groups
+            .entry(TokenTransferMultiKey {
+                from_addr: key.from_addr.clone(),
+                chain_id: key.chain_id,
+                token_addr: key.token_addr.clone(),
+            })
+            .or_default()
+            .push((min_id, key.clone()));
+    }
+
+    let mut waiting_for_funds = Vec::new();
+    for (multi_key, mut keys) in groups {
+        let web3 = payment_setup.get_provider(multi_key.chain_id)?;
+        let token_addr = multi_key
+            .token_addr
+            .as_ref()
+            .map(|addr| Address::from_str(addr))
+            .transpose()
+            .map_err(err_from!())?;
+        let balance = match get_balance(
+            web3,
+            token_addr,
+            Address::from_str(&multi_key.from_addr).map_err(err_from!())?,
+        )
+        .await
+        {
+            Ok(balance) => balance,
+            Err(err) => {
+                //funds are unknown, so do not send anything and check again on next gathering
+                log::warn!(
+                    "Failed to get balance of {} on chain {} (token {:?}), postponing transfers: {}",
+                    multi_key.from_addr,
+                    multi_key.chain_id,
+                    multi_key.token_addr,
+                    err
+                );
+                for (_, key) in keys {
+                    transfer_map.remove(&key);
+                }
+                continue;
+            }
+        };
+        let mut reserved = U256::zero();
+        for amount in get_reserved_token_amounts(
+            conn,
+            &multi_key.from_addr,
+            multi_key.chain_id,
+            multi_key.token_addr.as_deref(),
+        )
+        .await
+        .map_err(err_from!())?
+        {
+            reserved += U256::from_dec_str(&amount).map_err(err_from!())?;
+        }
+
+        let mut available = balance.saturating_sub(reserved);
+        let mut required = reserved;
+        let mut missing = U256::zero();
+        let mut transfer_ids = Vec::new();
+        keys.sort_by_key(|(min_id, _)| *min_id);
+        for (_, key) in keys {
+            let token_transfers = match transfer_map.get_mut(&key) {
+                Some(token_transfers) => token_transfers,
+                None => continue,
+            };
+            let mut sum = U256::zero();
+            for token_transfer in token_transfers.iter() {
+                sum += U256::from_dec_str(&token_transfer.token_amount).map_err(err_from!())?;
+            }
+            required += sum;
+            let covered = sum <= available && missing.is_zero();
+            if covered {
+                available -= sum;
+            } else {
+                missing += sum;
+                transfer_ids.extend(token_transfers.iter().map(|t| t.id));
+            }
+            //state is persisted, so it is known after restart and between gatherings
+            for token_transfer in token_transfers.iter_mut() {
+                if token_transfer.waiting_for_funds == covered {
+                    token_transfer.waiting_for_funds = !covered;
+                    update_token_transfer(conn, token_transfer)
+                        .await
+                        .map_err(err_from!())?;
+                }
+            }
+            if !covered {
+                transfer_map.remove(&key);
+            }
+        }
+        if !missing.is_zero() {
+            log::warn!(
+                "Account {} has not enough funds on chain {} (token {:?}), balance: {}, reserved: {}, waiting transfers amount: {}, transfers: {:?}",
+                multi_key.from_addr,
+                multi_key.chain_id,
+                multi_key.token_addr,
+                balance,
+                reserved,
+                missing,
+                transfer_ids
+            );
+            waiting_for_funds.push(WaitingForFunds {
+                from_addr: multi_key.from_addr,
+                chain_id: multi_key.chain_id,
+                token_addr: multi_key.token_addr,
+                balance,
+                required,
+            });
+        }
+    }
+    Ok(waiting_for_funds)
+}
+
+/// Keep transfers queued until their sum for the receiver reaches token min payout
+fn apply_min_payout(
+    payment_setup: &PaymentSetup,
+    transfer_map: &mut TokenTransferMap,
+) -> Result<(), PaymentError> {
+    //transfers kept unmerged still count towards the sum for their receiver
+    let mut receiver_sums = HashMap::<TokenTransferKey, (U256, U256, Vec<TokenTransferKey>)>::new();
+    for (key, token_transfers) in transfer_map.iter() {
+        let chain_setup = match payment_setup.chain_setup.get(&key.chain_id) {
+            Some(chain_setup) => chain_setup,
+            None => continue,
+        };
+        let token_addr = key
+            .token_addr
+            .as_ref()
+            .map(|addr| Address::from_str(addr))
+            .transpose()
+            .map_err(err_from!())?;
+        let min_payout = match chain_setup.get_min_payout(token_addr) {
+            Some(min_payout) => min_payout,
+            None => continue,
+        };
+        let entry = receiver_sums
+            .entry(TokenTransferKey {
+                transfer_id: None,
+                ..key.clone()
+            })
+            .or_insert_with(|| (min_payout, U256::zero(), Vec::new()));
+        for token_transfer in token_transfers {
+            entry.1 += U256::from_dec_str(&token_transfer.token_amount).map_err(err_from!())?;
+        }
+        entry.2.push(key.clone());
+    }
+    for (receiver_key, (min_payout, sum, keys)) in receiver_sums {
+        if sum < min_payout {
+            log::debug!(
+                "Sum {} for receiver {} below min payout {}, keeping transfers queued",
+                sum,
+                receiver_key.receiver_addr,
+                min_payout
+            );
+            for key in keys {
+                transfer_map.remove(&key);
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Remove transfers from the map which should wait for their batch to fill up
+/// according to the token or chain batching policy
+async fn apply_batching_policy(payment_setup: &PaymentSetup, transfer_map: &mut TokenTransferMap) {
+    if payment_setup.finish_when_done {
+        //there will be no next gathering, so send everything
+        return;
+    }
+    let mut groups = HashMap::<TokenTransferMultiKey, Vec<TokenTransferKey>>::new();
+    for key in transfer_map.keys() {
+        groups
+            .entry(TokenTransferMultiKey {
+                from_addr: key.from_addr.clone(),
+                chain_id: key.chain_id,
+                token_addr: key.token_addr.clone(),
+            })
+            .or_default()
+            .push(key.clone());
+    }
+    let mut gas_prices = HashMap::<i64, Option<U256>>::new();
+    for (multi_key, keys) in groups {
+        let token_addr = match multi_key
+            .token_addr
+            .as_ref()
+            .map(|addr| Address::from_str(addr))
+            .transpose()
+        {
+            Ok(token_addr) => token_addr,
+            Err(err) => {
+                log::warn!("Invalid token address {:?}: {}", multi_key.token_addr, err);
+                continue;
+            }
+        };
+        let policy = match payment_setup
+            .chain_setup
+            .get(&multi_key.chain_id)
+            .and_then(|chain_setup| chain_setup.get_batching_policy(token_addr))
+        {
+            Some(policy) => policy,
+            None => continue,
+        };
+        if keys.len() >= policy.min_batch_size {
+            continue;
+        }
+        //transfers created before created_date was tracked are treated as expired
+        let oldest = keys
+            .iter()
+            .filter_map(|key| transfer_map.get(key))
+            .flatten()
+            .map(|t| t.created_date)
+            .min()
+            .flatten();
+        let expired = match (oldest, policy.max_wait_time) {
+            (None, _) => true,
+            (Some(oldest), Some(max_wait_time)) => {
+                chrono::Utc::now() - oldest >= chrono::Duration::seconds(max_wait_time as i64)
+            }
+            (Some(_), None) => false,
+        };
+        //do not hold back transfers which would miss their deadline before next gathering
+        let next_gather =
+            chrono::Utc::now() + chrono::Duration::seconds(payment_setup.gather_interval as i64);
+        let deadline_close = keys
+            .iter()
+            .filter_map(|key| transfer_map.get(key))
+            .flatten()
+            .any(|t| t.deadline.map(|d| d <= next_gather).unwrap_or(false));
+        if expired || deadline_close {
+            continue;
+        }
+        if let Some(flush_gas_price) = policy.flush_gas_price {
+            let gas_price = match gas_prices.get(&multi_key.chain_id) {
+                Some(gas_price) => *gas_price,
+                None => {
+                    let gas_price = match payment_setup.get_provider(multi_key.chain_id) {
+                        Ok(web3) => match web3.eth().gas_price().await {
+                            Ok(gas_price) => Some(gas_price),
+                            Err(err) => {
+                                log::warn!("Failed to get gas price: {}", err);
+                                None
+                            }
+                        },
+                        Err(err) => {
+                            log::warn!("Failed to get provider: {}", err);
+                            None
+                        }
+                    };
+                    gas_prices.insert(multi_key.chain_id, gas_price);
+                    gas_price
+                }
+            };
+            if gas_price.map(|gp| gp <= flush_gas_price).unwrap_or(false) {
+                log::info!(
+                    "Gas price {} below threshold, flushing batch for {:?}",
+                    gas_price.unwrap_or_default(),
+                    multi_key
+                );
+                continue;
+            }
+        }
+        log::info!(
+            "Batch for {:?} waiting to fill up ({} of {} receivers)",
+            multi_key,
+            keys.len(),
+            policy.min_batch_size
+        );
+        for key in keys {
+            transfer_map.remove(&key);
+        }
+    }
+}
+
 pub async fn gather_transactions_batch_multi(
+    shared_state: Arc<Mutex<SharedState>>,
     conn: &SqlitePool,
     payment_setup: &PaymentSetup,
     multi_order_vector: &mut [TokenTransferMultiOrder],
@@ -115,66 +733,43 @@ pub async fn gather_transactions_batch_multi(
     let max_fee_per_gas = chain_setup.max_fee_per_gas;
     let priority_fee = chain_setup.priority_fee;
 
-    let max_per_batch = chain_setup.multi_contract_max_at_once;
+    let max_per_batch = shared_state.lock().await.get_max_batch_size(chain_setup);
     log::debug!("Processing token transfer {:?}", token_transfer);
     if let Some(token_addr) = token_transfer.token_addr.as_ref() {
-        if !payment_setup.skip_multi_contract_check {
+        //direct mode does not use allowance given to the multi contract
+        if !payment_setup.skip_multi_contract_check && !chain_setup.multi_contract_direct {
             if let Some(multi_contract_address) = chain_setup.multi_contract_address.as_ref() {
-                //this is some arbitrary number.
-                let minimum_allowance: U256 = U256::max_value() / U256::from(2);
-
-                let db_allowance = find_allowance(
+                let mut batch_amount = U256::zero();
+                for token_t in multi_order_vector.iter() {
+                    for token_transfer in &token_t.token_transfers {
+                        batch_amount += U256::from_dec_str(&token_transfer.token_amount)
+                            .map_err(err_from!())?;
+                    }
+                }
+                if let Some(allowance_request) = verify_allowance(
                     conn,
+                    payment_setup,
                     &token_transfer.from_addr,
                     token_addr,
                     &format!("{multi_contract_address:#x}"),
                     token_transfer.chain_id,
+                    batch_amount,
                 )
-                .await
-                .map_err(err_from!())?;
-
-                let mut allowance_not_met = false;
-                match db_allowance {
-                    Some(db_allowance) => match db_allowance.confirm_date {
-                        Some(_) => {
-                            let allowance =
-                                U256::from_dec_str(&db_allowance.allowance).map_err(err_from!())?;
-                            if allowance < minimum_allowance {
-                                log::debug!(
-                                    "Allowance already confirmed from db, but it is too small"
-                                );
-                                allowance_not_met = true;
-                            } else {
-                                log::debug!("Allowance confirmed from db");
-                            }
-                        }
-                        None => {
-                            log::debug!("Allowance request found, but not confirmed");
-                            allowance_not_met = true;
-                        }
-                    },
-                    None => {
-                        log::debug!("Allowance not found in db");
-                        allowance_not_met = true;
-                    }
-                };
-                if allowance_not_met {
-                    return Err(err_create!(AllowanceRequest {
-                        owner: token_transfer.from_addr.clone(),
-                        token_addr: token_addr.clone(),
-                        spender_addr: format!("{multi_contract_address:#x}"),
-                        chain_id: token_transfer.chain_id,
-                        amount: U256::max_value(),
-                    }));
+                .await?
+                {
+                    return Err(err_create!(allowance_request));
                 }
             }
         }
 
-        let split_orders = multi_order_vector
-            .chunks_mut(max_per_batch)
-            .collect::<Vec<_>>();
-
-        for smaller_order in split_orders {
+        let max_amount = chain_setup
+            .get_spending_limits(Some(Address::from_str(token_addr).map_err(err_from!())?))
+            .and_then(|limits| limits.max_per_batch);
+        let boundaries = get_batch_boundaries(multi_order_vector, max_per_batch, max_amount)?;
+        let mut start = 0;
+        for end in boundaries {
+            let smaller_order = &mut multi_order_vector[start..end];
+            start = end;
             let mut erc20_to = Vec::with_capacity(smaller_order.len());
             let mut erc20_amounts = Vec::with_capacity(smaller_order.len());
             for token_t in &mut *smaller_order {
@@ -219,7 +814,7 @@ pub async fn gather_transactions_batch_multi(
                         None,
                         max_fee_per_gas,
                         priority_fee,
-                        false,
+                        chain_setup.multi_contract_direct,
                     )?
                 }
             };
@@ -237,15 +832,106 @@ pub async fn gather_transactions_batch_multi(
                 }
             }
             db_transaction.commit().await.map_err(err_from!())?;
+            shared_state.lock().await.emit(PaymentEvent::TxCreated {
+                tx_id: web3_tx_dao.id,
+                chain_id: web3_tx_dao.chain_id,
+                method: web3_tx_dao.method.clone(),
+                from_addr: web3_tx_dao.from_addr.clone(),
+                transfer_ids: smaller_order
+                    .iter()
+                    .flat_map(|order| order.token_transfers.iter().map(|t| t.id))
+                    .collect(),
+            });
         }
     } else {
-        return Err(err_custom_create!("Not implemented for multi"));
+        let native_multi_contract_address = chain_setup
+            .native_multi_contract_address
+            .ok_or_else(|| err_custom_create!("Native multi contract not configured"))?;
+        let max_per_batch = chain_setup.native_multi_contract_max_at_once;
+        let max_amount = chain_setup
+            .get_spending_limits(None)
+            .and_then(|limits| limits.max_per_batch);
+        let boundaries = get_batch_boundaries(multi_order_vector, max_per_batch, max_amount)?;
+        let mut start = 0;
+        for end in boundaries {
+            let smaller_order = &mut multi_order_vector[start..end];
+            start = end;
+            let mut eth_to = Vec::with_capacity(smaller_order.len());
+            let mut eth_amounts = Vec::with_capacity(smaller_order.len());
+            for token_t in &mut *smaller_order {
+                let mut sum = U256::zero();
+                for token_transfer in &token_t.token_transfers {
+                    sum += U256::from_dec_str(&token_transfer.token_amount).map_err(err_from!())?;
+                }
+                eth_to.push(token_t.receiver);
+                eth_amounts.push(sum);
+            }
+
+            let web3tx = match eth_to.len() {
+                0 => {
+                    return Ok(0);
+                }
+                1 => {
+                    log::info!(
+                        "Inserting transaction stub for native transfer to: {:?}",
+                        eth_to[0]
+                    );
+                    create_eth_transfer(
+                        Address::from_str(&token_transfer.from_addr).map_err(err_from!())?,
+                        eth_to[0],
+                        token_transfer.chain_id as u64,
+                        None,
+                        max_fee_per_gas,
+                        priority_fee,
+                        eth_amounts[0],
+                    )
+                }
+                _ => {
+                    log::info!("Inserting transaction stub for native multi transfer contract: {:?} for {} distinct transfers", native_multi_contract_address, eth_to.len());
+                    create_eth_transfer_multi(
+                        Address::from_str(&token_transfer.from_addr).map_err(err_from!())?,
+                        native_multi_contract_address,
+                        eth_to,
+                        eth_amounts,
+                        token_transfer.chain_id as u64,
+                        None,
+                        max_fee_per_gas,
+                        priority_fee,
+                    )?
+                }
+            };
+            let mut db_transaction = conn.begin().await.map_err(err_from!())?;
+            let web3_tx_dao = insert_tx(&mut db_transaction, &web3tx)
+                .await
+                .map_err(err_from!())?;
+
+            for token_t in &mut *smaller_order {
+                for token_transfer in &mut token_t.token_transfers {
+                    token_transfer.tx_id = Some(web3_tx_dao.id);
+                    update_token_transfer(&mut db_transaction, token_transfer)
+                        .await
+                        .map_err(err_from!())?;
+                }
+            }
+            db_transaction.commit().await.map_err(err_from!())?;
+            shared_state.lock().await.emit(PaymentEvent::TxCreated {
+                tx_id: web3_tx_dao.id,
+                chain_id: web3_tx_dao.chain_id,
+                method: web3_tx_dao.method.clone(),
+                from_addr: web3_tx_dao.from_addr.clone(),
+                transfer_ids: smaller_order
+                    .iter()
+                    .flat_map(|order| order.token_transfers.iter().map(|t| t.id))
+                    .collect(),
+            });
+        }
     };
 
     Ok(1)
 }
 
 pub async fn gather_transactions_batch(
+    shared_state: Arc<Mutex<SharedState>>,
     conn: &SqlitePool,
     payment_setup: &PaymentSetup,
     token_transfers: &mut [TokenTransferDao],
@@ -262,7 +948,27 @@ pub async fn gather_transactions_batch(
     let priority_fee = chain_setup.priority_fee;
 
     log::debug!("Processing token transfer {:?}", token_transfer);
-    let web3tx = if let Some(token_addr) = token_transfer.token_addr.as_ref() {
+    let web3tx = if let (Some(token_addr), Some(relayer)) = (
+        token_transfer.token_addr.as_ref(),
+        chain_setup.meta_transaction_relayer,
+    ) {
+        log::info!(
+            "Inserting meta transaction stub for ERC20 transfer to: {} relayed by: {:#x}",
+            token_transfer.receiver_addr,
+            relayer
+        );
+        create_erc20_transfer_meta(
+            Address::from_str(&token_transfer.from_addr).map_err(err_from!())?,
+            relayer,
+            Address::from_str(token_addr).map_err(err_from!())?,
+            Address::from_str(&token_transfer.receiver_addr).map_err(err_from!())?,
+            sum,
+            token_transfer.chain_id as u64,
+            None,
+            max_fee_per_gas,
+            priority_fee,
+        )?
+    } else if let Some(token_addr) = token_transfer.token_addr.as_ref() {
         create_erc20_transfer(
             Address::from_str(&token_transfer.from_addr).map_err(err_from!())?,
             Address::from_str(token_addr).map_err(err_from!())?,
@@ -295,10 +1001,18 @@ pub async fn gather_transactions_batch(
             .map_err(err_from!())?;
     }
     db_transaction.commit().await.map_err(err_from!())?;
+    shared_state.lock().await.emit(PaymentEvent::TxCreated {
+        tx_id: web3_tx_dao.id,
+        chain_id: web3_tx_dao.chain_id,
+        method: web3_tx_dao.method.clone(),
+        from_addr: web3_tx_dao.from_addr.clone(),
+        transfer_ids: token_transfers.iter().map(|t| t.id).collect(),
+    });
     Ok(1)
 }
 
 pub async fn gather_transactions_post(
+    shared_state: Arc<Mutex<SharedState>>,
     conn: &SqlitePool,
     payment_setup: &PaymentSetup,
     token_transfer_map: &mut TokenTransferMap,
@@ -327,7 +1041,19 @@ pub async fn gather_transactions_post(
                 chain_id: key.1.chain_id,
                 token_addr: key.1.token_addr.clone(),
             };
-            if multi_key.token_addr.is_none() {
+            let chain_setup = payment_setup.chain_setup.get(&multi_key.chain_id);
+            let send_separately = if multi_key.token_addr.is_some() {
+                //meta transactions cannot be batched, relayer sends every transfer separately
+                chain_setup
+                    .map(|chain_setup| chain_setup.meta_transaction_relayer.is_some())
+                    .unwrap_or(false)
+            } else {
+                //native transfers are batched only through disperse contract
+                chain_setup
+                    .map(|chain_setup| chain_setup.native_multi_contract_address.is_none())
+                    .unwrap_or(true)
+            };
+            if send_separately {
                 let token_transfer = key.1;
                 let token_transfers = token_transfer_map
                     .get_mut(token_transfer)
@@ -335,6 +1061,7 @@ pub async fn gather_transactions_post(
 
                 //sum of transfers
                 match gather_transactions_batch(
+                    shared_state.clone(),
                     conn,
                     payment_setup,
                     token_transfers,
@@ -355,7 +1082,7 @@ pub async fn gather_transactions_post(
                                 //mark other errors in db to not process these failed transfers again
                                 for token_transfer in token_transfers {
                                     token_transfer.error =
-                                        Some("Error in gathering transactions".to_string());
+                                        Some(format!("{GATHER_ERROR_PREFIX}: {}", e.inner));
                                     update_token_transfer(conn, token_transfer)
                                         .await
                                         .map_err(err_from!())?;
@@ -397,6 +1124,7 @@ pub async fn gather_transactions_post(
             let mut token_transfers = key.1.clone();
             //todo fix clones
             match gather_transactions_batch_multi(
+                shared_state.clone(),
                 conn,
                 payment_setup,
                 &mut token_transfers,
@@ -418,7 +1146,7 @@ pub async fn gather_transactions_post(
                             for multi in token_transfers {
                                 for token_transfer in multi.token_transfers {
                                     let mut tt = token_transfer.clone();
-                                    tt.error = Some("Error in gathering transactions".to_string());
+                                    tt.error = Some(format!("{GATHER_ERROR_PREFIX}: {}", e.inner));
                                     update_token_transfer(conn, &tt)
                                         .await
                                         .map_err(err_from!())?;
@@ -435,3 +1163,191 @@ pub async fn gather_transactions_post(
 
     Ok(inserted_tx_count)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::Config;
+    use crate::transaction::create_token_transfer;
+
+    const TOKEN_ADDR: &str = "0x2036807b0b3aaf5b1858ee822d0e111fddac7018";
+
+    fn test_payment_setup() -> PaymentSetup {
+        let config = Config::load_from_str(&format!(
+            r#"
+[engine]
+service-sleep = 1
+process-sleep = 1
+automatic-recover = false
+
+[chain.dev]
+chain-name = "Golem testnet"
+chain-id = 987789
+rpc-endpoints = ["http://127.0.0.1:8545"]
+currency-symbol = "tETH"
+priority-fee = 1.1
+max-fee-per-gas = 500.0
+gas-left-warning-limit = 1000000
+transaction-timeout = 100
+confirmation-blocks = 1
+native-min-payout = 0.5
+batching = {{ min-batch-size = 2, max-wait-time = 3600 }}
+token = {{ address = "{TOKEN_ADDR}", symbol = "tGLM", min-payout = 1.0 }}
+"#
+        ))
+        .unwrap();
+        PaymentSetup::new(&config, vec![], vec![], false, false, false, 1, 1, false).unwrap()
+    }
+
+    fn ether(amount: u64) -> U256 {
+        U256::from(amount) * U256::exp10(18)
+    }
+
+    fn add_transfer(
+        transfer_map: &mut TokenTransferMap,
+        id: i64,
+        receiver: u64,
+        token_addr: Option<Address>,
+        amount: U256,
+    ) -> TokenTransferKey {
+        let mut token_transfer = create_token_transfer(
+            Address::from_low_u64_be(1),
+            Address::from_low_u64_be(receiver),
+            987789,
+            None,
+            token_addr,
+            amount,
+        );
+        token_transfer.id = id;
+        let key = TokenTransferKey {
+            from_addr: token_transfer.from_addr.clone(),
+            receiver_addr: token_transfer.receiver_addr.clone(),
+            chain_id: token_transfer.chain_id,
+            token_addr: token_transfer.token_addr.clone(),
+            transfer_id: None,
+        };
+        transfer_map
+            .entry(key.clone())
+            .or_default()
+            .push(token_transfer);
+        key
+    }
+
+    fn order(receiver: u64, amount: u64) -> TokenTransferMultiOrder {
+        TokenTransferMultiOrder {
+            receiver: Address::from_low_u64_be(receiver),
+            token_transfers: vec![create_token_transfer(
+                Address::from_low_u64_be(1),
+                Address::from_low_u64_be(receiver),
+                987789,
+                None,
+                None,
+                U256::from(amount),
+            )],
+        }
+    }
+
+    #[test]
+    fn test_spending_limits() {
+        let limits = SpendingLimitsSetup {
+            max_per_transfer: Some(U256::from(100)),
+            max_per_receiver_per_transaction: Some(U256::from(150)),
+            max_per_sender_daily: Some(U256::from(1000)),
+            ..Default::default()
+        };
+        let zero = U256::zero();
+        //amount exactly at the limit is allowed
+        assert_eq!(
+            get_breached_limit(&limits, U256::from(100), zero, zero, zero),
+            None
+        );
+        assert_eq!(
+            get_breached_limit(&limits, U256::from(101), zero, zero, zero),
+            Some("max per transfer")
+        );
+        assert_eq!(
+            get_breached_limit(&limits, U256::from(50), U256::from(100), zero, zero),
+            None
+        );
+        assert_eq!(
+            get_breached_limit(&limits, U256::from(51), U256::from(100), zero, zero),
+            Some("max per receiver per transaction")
+        );
+        assert_eq!(
+            get_breached_limit(&limits, U256::from(1), zero, zero, U256::from(1000)),
+            Some("max per sender daily")
+        );
+
+        //single transfer over per receiver limit is held even without other transfers
+        let limits = SpendingLimitsSetup {
+            max_per_receiver_per_transaction: Some(U256::from(150)),
+            ..Default::default()
+        };
+        assert_eq!(
+            get_breached_limit(&limits, U256::from(151), zero, zero, zero),
+            Some("max per receiver per transaction")
+        );
+    }
+
+    #[test]
+    fn test_batch_boundaries() {
+        let orders = vec![order(2, 40), order(3, 60), order(4, 30), order(5, 200)];
+        assert_eq!(get_batch_boundaries(&orders, 10, None).unwrap(), vec![4]);
+        assert_eq!(get_batch_boundaries(&orders, 3, None).unwrap(), vec![3, 4]);
+        //sum exactly at the limit stays in one batch, order over the limit gets its own batch
+        assert_eq!(
+            get_batch_boundaries(&orders, 10, Some(U256::from(100))).unwrap(),
+            vec![2, 3, 4]
+        );
+        assert!(get_batch_boundaries(&[], 10, None).unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_min_payout() {
+        let payment_setup = test_payment_setup();
+        let token_addr = Some(Address::from_str(TOKEN_ADDR).unwrap());
+        let other_token_addr = Some(Address::from_low_u64_be(0x1234));
+
+        let mut transfer_map = TokenTransferMap::new();
+        let below = add_transfer(&mut transfer_map, 1, 2, token_addr, ether(1) / 2);
+        let reached = add_transfer(&mut transfer_map, 2, 3, token_addr, ether(1) / 2);
+        add_transfer(&mut transfer_map, 3, 3, token_addr, ether(1) / 2);
+        let native_below = add_transfer(&mut transfer_map, 4, 2, None, ether(1) / 4);
+        let native_reached = add_transfer(&mut transfer_map, 5, 3, None, ether(1) / 2);
+        let other_token = add_transfer(&mut transfer_map, 6, 2, other_token_addr, U256::one());
+
+        apply_min_payout(&payment_setup, &mut transfer_map).unwrap();
+        assert!(!transfer_map.contains_key(&below));
+        assert_eq!(transfer_map.get(&reached).map(|t| t.len()), Some(2));
+        assert!(!transfer_map.contains_key(&native_below));
+        assert!(transfer_map.contains_key(&native_reached));
+        assert!(transfer_map.contains_key(&other_token));
+    }
+
+    #[tokio::test]
+    async fn test_batching_policy() {
+        let payment_setup = test_payment_setup();
+        let token_addr = Some(Address::from_str(TOKEN_ADDR).unwrap());
+
+        let mut transfer_map = TokenTransferMap::new();
+        //single receiver waits for the batch to fill up
+        let waiting = add_transfer(&mut transfer_map, 1, 2, None, U256::one());
+        //two receivers fill the batch
+        let full = add_transfer(&mut transfer_map, 2, 2, token_addr, U256::one());
+        add_transfer(&mut transfer_map, 3, 3, token_addr, U256::one());
+        apply_batching_policy(&payment_setup, &mut transfer_map).await;
+        assert!(!transfer_map.contains_key(&waiting));
+        assert!(transfer_map.contains_key(&full));
+        assert_eq!(transfer_map.len(), 2);
+
+        //batch waiting longer than max wait time is sent
+        let mut transfer_map = TokenTransferMap::new();
+        let expired = add_transfer(&mut transfer_map, 1, 2, None, U256::one());
+        for token_transfer in transfer_map.get_mut(&expired).unwrap() {
+            token_transfer.created_date =
+                Some(chrono::Utc::now() - chrono::Duration::seconds(3600));
+        }
+        apply_batching_policy(&payment_setup, &mut transfer_map).await;
+        assert!(transfer_map.contains_key(&expired));
+    }
+}
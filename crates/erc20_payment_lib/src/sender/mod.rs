@@ -1,8 +1,20 @@
 mod allowance;
+mod approval;
 mod batching;
+mod operator;
 pub mod process;
+mod queue;
+mod recurring;
+mod retry;
 mod service;
+mod simulate;
 
 pub use allowance::*;
+pub use approval::*;
 pub use batching::*;
+pub use operator::*;
+pub use queue::*;
+pub use recurring::*;
+pub use retry::*;
 pub use service::*;
+pub use simulate::*;
@@ -0,0 +1,293 @@
+use crate::db::model::*;
+use crate::db::ops::*;
+use crate::error::{CustomError, ErrorBag, PaymentError};
+use crate::events::PaymentEvent;
+use crate::runtime::SharedState;
+use crate::sender::process::ProcessTransactionResult;
+use crate::sender::{requeue_failed_transfers, update_result_by_method};
+use crate::{err_custom_create, err_from};
+
+use chrono::Utc;
+use sqlx::SqlitePool;
+use std::sync::Arc;
+use tokio::sync::Mutex;
+
+async fn get_tx(conn: &SqlitePool, tx_id: i64) -> Result<TxDao, PaymentError> {
+    match get_transaction(conn, tx_id).await {
+        Ok(tx) => Ok(tx),
+        Err(sqlx::Error::RowNotFound) => Err(err_custom_create!("Transaction {} not found", tx_id)),
+        Err(err) => Err(err_from!()(err)),
+    }
+}
+
+pub async fn is_tx_held(conn: &SqlitePool, tx_id: i64) -> Result<bool, PaymentError> {
+    let filter = format!("id = {tx_id} AND {TRANSACTION_FILTER_HELD}");
+    Ok(!get_transactions(conn, Some(&filter), Some(1), None)
+        .await
+        .map_err(err_from!())?
+        .is_empty())
+}
+
+/// Save operator action, it is applied by the engine on next processing loop
+/// (right away when the engine is running, on next start otherwise)
+pub async fn record_operator_action(
+    conn: &SqlitePool,
+    tx_id: i64,
+    action: &str,
+    actor: &str,
+    reason: &str,
+) -> Result<OperatorActionDao, PaymentError> {
+    if !OPERATOR_ACTIONS.contains(&action) {
+        return Err(err_custom_create!(
+            "Unknown action {}, expected one of {}",
+            action,
+            OPERATOR_ACTIONS.join(", ")
+        ));
+    }
+    check_actor_and_reason(actor, reason)?;
+    let tx = get_tx(conn, tx_id).await?;
+    match action {
+        OPERATOR_ACTION_RETRY if tx.processing > 0 || tx.error.is_none() => {
+            return Err(err_custom_create!(
+                "Only failed transactions can be retried, transaction {} has not failed",
+                tx_id
+            ));
+        }
+        OPERATOR_ACTION_RETRY if tx.broadcast_date.is_some() => {
+            return Err(err_custom_create!(
+                "Transaction {} was already sent and may still be confirmed, it cannot be retried",
+                tx_id
+            ));
+        }
+        OPERATOR_ACTION_RETRY => {}
+        _ if tx.processing == 0 => {
+            return Err(err_custom_create!(
+                "Transaction {} is already finished",
+                tx_id
+            ));
+        }
+        OPERATOR_ACTION_CANCEL if tx.broadcast_date.is_some() => {
+            return Err(err_custom_create!(
+                "Transaction {} was already sent, it can be skipped only",
+                tx_id
+            ));
+        }
+        OPERATOR_ACTION_HOLD if is_tx_held(conn, tx_id).await? => {
+            return Err(err_custom_create!("Transaction {} is already held", tx_id));
+        }
+        OPERATOR_ACTION_RELEASE if !is_tx_held(conn, tx_id).await? => {
+            return Err(err_custom_create!("Transaction {} is not held", tx_id));
+        }
+        _ => {}
+    }
+    let operator_action = insert_operator_action(
+        conn,
+        &OperatorActionDao {
+            id: 0,
+            tx_id: Some(tx_id),
+            transfer_id: None,
+            action: action.to_string(),
+            actor: actor.to_string(),
+            reason: reason.to_string(),
+            created_date: Utc::now(),
+            processed_date: None,
+            result: None,
+        },
+    )
+    .await
+    .map_err(err_from!())?;
+    log::info!(
+        "Operator action {} of transaction {} recorded by {}: {}",
+        action,
+        tx_id,
+        actor,
+        reason
+    );
+    Ok(operator_action)
+}
+
+/// Save manual queue repair done outside of the engine, it is recorded as already applied
+pub async fn record_applied_operator_action(
+    conn: &SqlitePool,
+    tx_id: Option<i64>,
+    transfer_id: Option<i64>,
+    action: &str,
+    actor: &str,
+    reason: &str,
+    result: &str,
+) -> Result<OperatorActionDao, PaymentError> {
+    let operator_action = insert_operator_action(
+        conn,
+        &OperatorActionDao {
+            id: 0,
+            tx_id,
+            transfer_id,
+            action: action.to_string(),
+            actor: actor.to_string(),
+            reason: reason.to_string(),
+            created_date: Utc::now(),
+            processed_date: Some(Utc::now()),
+            result: Some(result.to_string()),
+        },
+    )
+    .await
+    .map_err(err_from!())?;
+    log::info!(
+        "Operator action {} (transaction {:?}, transfer {:?}) by {}: {}",
+        action,
+        tx_id,
+        transfer_id,
+        actor,
+        reason
+    );
+    Ok(operator_action)
+}
+
+/// Actor and reason are required for every operator action
+pub fn check_actor_and_reason(actor: &str, reason: &str) -> Result<(), PaymentError> {
+    if actor.trim().is_empty() || reason.trim().is_empty() {
+        return Err(err_custom_create!("Actor and reason are required"));
+    }
+    Ok(())
+}
+
+/// Stop processing transaction with given error set on its transfers
+async fn fail_tx(
+    shared_state: &Arc<Mutex<SharedState>>,
+    conn: &SqlitePool,
+    mut tx: TxDao,
+    error: String,
+) -> Result<(), PaymentError> {
+    update_result_by_method(
+        conn,
+        &mut tx,
+        &ProcessTransactionResult::InternalError(error.clone()),
+    )
+    .await?;
+    let mut shared_state = shared_state.lock().await;
+    shared_state.delete_tx_info(tx.id);
+    shared_state.emit(PaymentEvent::TxFailed {
+        tx_id: tx.id,
+        chain_id: tx.chain_id,
+        error,
+    });
+    Ok(())
+}
+
+async fn apply_operator_action(
+    shared_state: &Arc<Mutex<SharedState>>,
+    conn: &SqlitePool,
+    operator_action: &OperatorActionDao,
+) -> Result<String, PaymentError> {
+    let tx_id = operator_action.tx_id.ok_or_else(|| {
+        err_custom_create!("Operator action {} has no transaction", operator_action.id)
+    })?;
+    let tx = get_tx(conn, tx_id).await?;
+    match operator_action.action.as_str() {
+        OPERATOR_ACTION_HOLD => {
+            if tx.nonce.is_some() {
+                log::warn!(
+                    "Transaction {} held with nonce assigned, next transactions of {} will wait for it",
+                    tx.id,
+                    tx.from_addr
+                );
+            }
+            Ok("Held".to_string())
+        }
+        OPERATOR_ACTION_RELEASE => Ok("Released".to_string()),
+        OPERATOR_ACTION_SKIP | OPERATOR_ACTION_CANCEL => {
+            if tx.processing == 0 {
+                return Err(err_custom_create!(
+                    "Transaction {} is already finished",
+                    tx.id
+                ));
+            }
+            let error = if operator_action.action == OPERATOR_ACTION_SKIP {
+                format!(
+                    "Skipped by {}: {}",
+                    operator_action.actor, operator_action.reason
+                )
+            } else {
+                if tx.broadcast_date.is_some() {
+                    return Err(err_custom_create!("Transaction {} was already sent", tx.id));
+                }
+                format!(
+                    "Cancelled by {}: {}",
+                    operator_action.actor, operator_action.reason
+                )
+            };
+            fail_tx(shared_state, conn, tx, error).await?;
+            Ok("Transaction stopped, transfers marked as failed".to_string())
+        }
+        OPERATOR_ACTION_RETRY => {
+            if tx.processing > 0 {
+                return Err(err_custom_create!(
+                    "Transaction {} is still processing",
+                    tx.id
+                ));
+            }
+            if tx.broadcast_date.is_some() {
+                return Err(err_custom_create!("Transaction {} was already sent", tx.id));
+            }
+            let mut requeued = 0;
+            for token_transfer in get_token_transfers_by_tx(conn, tx.id)
+                .await
+                .map_err(err_from!())?
+            {
+                if token_transfer.error.is_some() {
+                    requeued += requeue_failed_transfers(
+                        conn,
+                        Some(token_transfer.id),
+                        false,
+                        &operator_action.actor,
+                        &operator_action.reason,
+                    )
+                    .await?
+                    .len();
+                }
+            }
+            Ok(format!("{requeued} transfers returned to queue"))
+        }
+        action => Err(err_custom_create!("Unknown action {}", action)),
+    }
+}
+
+/// Apply operator actions not processed yet, failed action is recorded with its error
+pub async fn apply_operator_actions(
+    shared_state: &Arc<Mutex<SharedState>>,
+    conn: &SqlitePool,
+) -> Result<usize, PaymentError> {
+    let operator_actions = get_unprocessed_operator_actions(conn)
+        .await
+        .map_err(err_from!())?;
+    for mut operator_action in operator_actions.iter().cloned() {
+        let result = match apply_operator_action(shared_state, conn, &operator_action).await {
+            Ok(result) => {
+                log::info!(
+                    "Operator action {} of transaction {:?} by {} applied: {}",
+                    operator_action.action,
+                    operator_action.tx_id,
+                    operator_action.actor,
+                    result
+                );
+                result
+            }
+            Err(err) => {
+                log::error!(
+                    "Operator action {} of transaction {:?} by {} failed: {}",
+                    operator_action.action,
+                    operator_action.tx_id,
+                    operator_action.actor,
+                    err
+                );
+                format!("Failed: {}", err.inner)
+            }
+        };
+        operator_action.processed_date = Some(Utc::now());
+        operator_action.result = Some(result);
+        update_operator_action(conn, &operator_action)
+            .await
+            .map_err(err_from!())?;
+    }
+    Ok(operator_actions.len())
+}
@@ -11,14 +11,18 @@ use web3::transports::Http;
 use web3::types::{Address, U256};
 use web3::Web3;
 
+use crate::contracts::decode_multi_packed_receivers_count;
 use crate::db::model::TxDao;
 use crate::eth::get_transaction_count;
-use crate::runtime::SharedState;
-use crate::setup::PaymentSetup;
+use crate::events::PaymentEvent;
+use crate::runtime::{SharedState, ShutdownToken};
+use crate::setup::{ChainSetup, PaymentSetup};
 use crate::signer::Signer;
 use crate::transaction::check_transaction;
 use crate::transaction::find_receipt;
 use crate::transaction::send_transaction;
+use crate::transaction::sign_meta_transaction;
+use crate::transaction::sign_permit;
 use crate::transaction::sign_transaction_with_callback;
 use crate::utils::u256_to_rust_dec;
 
@@ -30,6 +34,15 @@ pub enum ProcessTransactionResult {
     Unknown,
 }
 
+/// Number of receivers of multi transfer contract transaction, None for other transactions
+pub fn get_multi_receivers_count(web3_tx_dao: &TxDao) -> Option<usize> {
+    if !web3_tx_dao.method.starts_with("MULTI.golemTransfer") {
+        return None;
+    }
+    let call_data = hex::decode(web3_tx_dao.call_data.as_ref()?).ok()?;
+    decode_multi_packed_receivers_count(&call_data)
+}
+
 #[allow(dead_code)]
 pub async fn get_provider(url: &str) -> Result<Web3<Http>, PaymentError> {
     let transport = web3::transports::Http::new(url).map_err(err_from!())?;
@@ -37,12 +50,39 @@ pub async fn get_provider(url: &str) -> Result<Web3<Http>, PaymentError> {
     Ok(web3)
 }
 
+/// Report failing RPC endpoint to event subscribers
+async fn provider_error(
+    shared_state: &Arc<Mutex<SharedState>>,
+    chain_setup: &ChainSetup,
+    err: web3::Error,
+) -> PaymentError {
+    let error = format!(
+        "Web3 RPC endpoint failing for network {}(chainId: {}): {}",
+        chain_setup.chain_name, chain_setup.chain_id, err
+    );
+    shared_state.lock().await.emit(PaymentEvent::ProviderError {
+        chain_id: chain_setup.chain_id,
+        error: error.clone(),
+    });
+    err_custom_create!("{}", error)
+}
+
+async fn emit_tx_broadcast(shared_state: &Arc<Mutex<SharedState>>, web3_tx_dao: &TxDao) {
+    shared_state.lock().await.emit(PaymentEvent::TxBroadcast {
+        tx_id: web3_tx_dao.id,
+        chain_id: web3_tx_dao.chain_id,
+        tx_hash: web3_tx_dao.tx_hash.clone(),
+        broadcast_count: web3_tx_dao.broadcast_count,
+    });
+}
+
 pub async fn process_transaction(
     shared_state: Arc<Mutex<SharedState>>,
     conn: &SqlitePool,
     web3_tx_dao: &mut TxDao,
     payment_setup: &PaymentSetup,
     signer: &impl Signer,
+    shutdown: &ShutdownToken,
     wait_for_confirmation: bool,
 ) -> Result<ProcessTransactionResult, PaymentError> {
     const CHECKS_UNTIL_NOT_FOUND: u64 = 5;
@@ -81,16 +121,10 @@ pub async fn process_transaction(
             .await
             .set_tx_message(web3_tx_dao.id, "Obtaining transaction nonce".to_string());
 
-        let nonce = get_transaction_count(from_addr, web3, false)
-            .await
-            .map_err(|err| {
-                err_custom_create!(
-                    "Web3 RPC endpoint failing for network {}(chainId: {}): {}",
-                    chain_setup.chain_name,
-                    chain_setup.chain_id,
-                    err
-                )
-            })? as i64;
+        let nonce = match get_transaction_count(from_addr, web3, false).await {
+            Ok(nonce) => nonce as i64,
+            Err(err) => return Err(provider_error(&shared_state, chain_setup, err).await),
+        };
         web3_tx_dao.nonce = Some(nonce);
         nonce
     };
@@ -110,6 +144,13 @@ pub async fn process_transaction(
         let expected_gas_balance =
             chain_setup.max_fee_per_gas * U256::from(chain_setup.gas_left_warning_limit);
         if gas_balance < expected_gas_balance {
+            shared_state.lock().await.emit(PaymentEvent::LowBalance {
+                chain_id,
+                account: web3_tx_dao.from_addr.clone(),
+                token_addr: None,
+                balance: gas_balance,
+                required: expected_gas_balance,
+            });
             let msg = if gas_balance.is_zero() {
                 format!("Account {} gas balance", chain_setup.currency_gas_symbol)
             } else {
@@ -146,7 +187,27 @@ pub async fn process_transaction(
         update_tx(conn, web3_tx_dao).await.map_err(err_from!())?;
     }
 
+    //every step below is persisted with update_tx, so on shutdown it is safe
+    //to stop between them and continue with the next step after restart
     if web3_tx_dao.signed_raw_data.is_none() {
+        if shutdown.is_shutdown() {
+            return Ok(ProcessTransactionResult::Unknown);
+        }
+        if web3_tx_dao.method.starts_with("META.") {
+            shared_state
+                .lock()
+                .await
+                .set_tx_message(web3_tx_dao.id, "Signing meta transaction".to_string());
+            sign_meta_transaction(web3, web3_tx_dao, signer).await?;
+            update_tx(conn, web3_tx_dao).await.map_err(err_from!())?;
+        } else if web3_tx_dao.method == "ERC20.permit" {
+            shared_state
+                .lock()
+                .await
+                .set_tx_message(web3_tx_dao.id, "Signing permit".to_string());
+            sign_permit(web3, web3_tx_dao, signer).await?;
+            update_tx(conn, web3_tx_dao).await.map_err(err_from!())?;
+        }
         shared_state
             .lock()
             .await
@@ -172,15 +233,35 @@ pub async fn process_transaction(
             }
         }
         log::debug!("web3_tx_dao after check_transaction: {:?}", web3_tx_dao);
+        if let (Some(gas_limit), Some(receivers)) = (
+            web3_tx_dao.gas_limit,
+            get_multi_receivers_count(web3_tx_dao),
+        ) {
+            shared_state
+                .lock()
+                .await
+                .record_batch_gas(chain_id, gas_limit as u64, receivers);
+        }
         shared_state
             .lock()
             .await
             .set_tx_message(web3_tx_dao.id, "Signing transaction".to_string());
         sign_transaction_with_callback(web3_tx_dao, from_addr, signer).await?;
         update_tx(conn, web3_tx_dao).await.map_err(err_from!())?;
+        shared_state.lock().await.emit(PaymentEvent::TxSigned {
+            tx_id: web3_tx_dao.id,
+            chain_id,
+        });
     }
 
     if web3_tx_dao.broadcast_date.is_none() {
+        if shutdown.is_shutdown() {
+            log::info!(
+                "Shutdown requested, signed transaction {} will be sent after restart",
+                web3_tx_dao.id
+            );
+            return Ok(ProcessTransactionResult::Unknown);
+        }
         log::info!(
             "Sending transaction {} with nonce {}",
             web3_tx_dao.id,
@@ -193,6 +274,7 @@ pub async fn process_transaction(
         send_transaction(web3, web3_tx_dao).await?;
         web3_tx_dao.broadcast_count += 1;
         update_tx(conn, web3_tx_dao).await.map_err(err_from!())?;
+        emit_tx_broadcast(&shared_state, web3_tx_dao).await;
         log::info!(
             "Transaction {} sent, tx hash: {}",
             web3_tx_dao.id,
@@ -217,30 +299,15 @@ pub async fn process_transaction(
             web3_tx_dao.id,
             transaction_nonce + 1
         );
-        let latest_nonce = get_transaction_count(from_addr, web3, false)
-            .await
-            .map_err(|err| {
-                err_custom_create!(
-                    "Web3 RPC endpoint failing for network {}(chainId: {}): {}",
-                    chain_setup.chain_name,
-                    chain_setup.chain_id,
-                    err
-                )
-            })?;
+        let latest_nonce = match get_transaction_count(from_addr, web3, false).await {
+            Ok(latest_nonce) => latest_nonce,
+            Err(err) => return Err(provider_error(&shared_state, chain_setup, err).await),
+        };
 
-        let current_block_number = web3
-            .eth()
-            .block_number()
-            .await
-            .map_err(|err| {
-                err_custom_create!(
-                    "Web3 RPC endpoint failing for network {}(chainId: {}): {}",
-                    chain_setup.chain_name,
-                    chain_setup.chain_id,
-                    err
-                )
-            })?
-            .as_u64();
+        let current_block_number = match web3.eth().block_number().await {
+            Ok(block_number) => block_number.as_u64(),
+            Err(err) => return Err(provider_error(&shared_state, chain_setup, err).await),
+        };
 
         if latest_nonce
             > web3_tx_dao
@@ -330,13 +397,15 @@ pub async fn process_transaction(
             send_transaction(web3, web3_tx_dao).await?;
             web3_tx_dao.broadcast_count += 1;
             update_tx(conn, web3_tx_dao).await.map_err(err_from!())?;
-            tokio::time::sleep(wait_duration).await;
+            emit_tx_broadcast(&shared_state, web3_tx_dao).await;
+            if !shutdown.sleep(wait_duration).await {
+                return Ok(ProcessTransactionResult::Unknown);
+            }
             continue;
         }
-        if !wait_for_confirmation {
+        if !wait_for_confirmation || !shutdown.sleep(wait_duration).await {
             return Ok(ProcessTransactionResult::Unknown);
         }
-        tokio::time::sleep(wait_duration).await;
     }
     log::debug!("web3_tx_dao after confirmation: {:?}", web3_tx_dao);
     Ok(ProcessTransactionResult::Confirmed)
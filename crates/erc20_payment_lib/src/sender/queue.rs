@@ -0,0 +1,206 @@
+use crate::db::model::*;
+use crate::db::ops::*;
+use crate::error::{CustomError, ErrorBag, PaymentError};
+use crate::sender::process::ProcessTransactionResult;
+use crate::sender::{
+    check_actor_and_reason, is_transfer_method, record_applied_operator_action,
+    return_transfers_to_queue, update_result_by_method,
+};
+use crate::{err_custom_create, err_from};
+
+use sqlx::SqlitePool;
+
+/// Clear error and transaction of failed transfers, so they are gathered again,
+/// all failed transfers are requeued when id is not given.
+/// Transfers of sent transactions are requeued only with force, because the sent
+/// transaction can still be confirmed and the receiver would be paid twice.
+/// Every requeued transfer is recorded as operator action.
+pub async fn requeue_failed_transfers(
+    conn: &SqlitePool,
+    transfer_id: Option<i64>,
+    force: bool,
+    actor: &str,
+    reason: &str,
+) -> Result<Vec<TokenTransferDao>, PaymentError> {
+    check_actor_and_reason(actor, reason)?;
+    let token_transfers = match transfer_id {
+        Some(transfer_id) => vec![get_token_transfer(conn, transfer_id)
+            .await
+            .map_err(err_from!())?
+            .ok_or_else(|| err_custom_create!("Transfer {} not found", transfer_id))?],
+        None => get_token_transfers(conn, Some(TRANSFER_FILTER_FAILED), None)
+            .await
+            .map_err(err_from!())?,
+    };
+    let mut requeued = Vec::with_capacity(token_transfers.len());
+    for mut token_transfer in token_transfers {
+        if let Some(reason) = get_requeue_blocker(conn, &token_transfer, force).await? {
+            if transfer_id.is_some() {
+                return Err(err_custom_create!("{}", reason));
+            }
+            log::debug!("Skipping requeue: {}", reason);
+            continue;
+        }
+        let result = format!(
+            "Requeued, previous error: {}",
+            token_transfer.error.as_deref().unwrap_or_default()
+        );
+        let tx_id = token_transfer.tx_id;
+        token_transfer.error = None;
+        token_transfer.tx_id = None;
+        token_transfer.fee_paid = None;
+        token_transfer.dead_letter = false;
+        update_token_transfer(conn, &token_transfer)
+            .await
+            .map_err(err_from!())?;
+        record_applied_operator_action(
+            conn,
+            tx_id,
+            Some(token_transfer.id),
+            OPERATOR_ACTION_REQUEUE,
+            actor,
+            reason,
+            &result,
+        )
+        .await?;
+        requeued.push(token_transfer);
+    }
+    Ok(requeued)
+}
+
+/// Reason why the transfer cannot be requeued
+async fn get_requeue_blocker(
+    conn: &SqlitePool,
+    token_transfer: &TokenTransferDao,
+    force: bool,
+) -> Result<Option<String>, PaymentError> {
+    if token_transfer.error.is_none() {
+        return Ok(Some(format!(
+            "Transfer {} has not failed",
+            token_transfer.id
+        )));
+    }
+    if let Some(tx_id) = token_transfer.tx_id {
+        let tx = get_transaction(conn, tx_id).await.map_err(err_from!())?;
+        if tx.processing > 0 {
+            return Ok(Some(format!(
+                "Transaction {} of transfer {} is still processing",
+                tx_id, token_transfer.id
+            )));
+        }
+        if tx.broadcast_date.is_some() || tx.tx_hash.is_some() {
+            if !force {
+                return Ok(Some(format!(
+                    "Transaction {} of transfer {} was already sent and may still be confirmed, use force if it is known to be dropped",
+                    tx_id, token_transfer.id
+                )));
+            }
+            log::warn!(
+                "Requeueing transfer {} of sent transaction {}, receiver is paid twice if the transaction gets confirmed",
+                token_transfer.id,
+                tx_id
+            );
+        }
+    }
+    if let Some(retry) = get_token_transfer_retry(conn, token_transfer.id)
+        .await
+        .map_err(err_from!())?
+    {
+        return Ok(Some(format!(
+            "Transfer {} was already retried as transfer {}",
+            token_transfer.id, retry.id
+        )));
+    }
+    Ok(None)
+}
+
+async fn get_processing_transaction(conn: &SqlitePool, tx_id: i64) -> Result<TxDao, PaymentError> {
+    let tx = get_transaction(conn, tx_id).await.map_err(err_from!())?;
+    if tx.processing == 0 {
+        return Err(err_custom_create!(
+            "Transaction {} is already finished",
+            tx_id
+        ));
+    }
+    Ok(tx)
+}
+
+/// Stop processing transaction, its transfers (or allowance) are marked as failed.
+/// Sent transaction is marked only with force, because it can still be confirmed
+pub async fn mark_tx_failed(
+    conn: &SqlitePool,
+    tx_id: i64,
+    actor: &str,
+    reason: &str,
+    force: bool,
+) -> Result<TxDao, PaymentError> {
+    check_actor_and_reason(actor, reason)?;
+    let mut tx = get_processing_transaction(conn, tx_id).await?;
+    if tx.broadcast_date.is_some() || tx.tx_hash.is_some() {
+        if !force {
+            return Err(err_custom_create!(
+                "Transaction {} was already sent and may still be confirmed, use force if it is known to be dropped",
+                tx_id
+            ));
+        }
+        log::warn!(
+            "Marking sent transaction {} as failed, it may still be confirmed",
+            tx_id
+        );
+    }
+    update_result_by_method(
+        conn,
+        &mut tx,
+        &ProcessTransactionResult::InternalError(format!("Marked as failed by {actor}: {reason}")),
+    )
+    .await?;
+    record_applied_operator_action(
+        conn,
+        Some(tx_id),
+        None,
+        OPERATOR_ACTION_MARK_FAILED,
+        actor,
+        reason,
+        "Transaction stopped, transfers marked as failed",
+    )
+    .await?;
+    Ok(tx)
+}
+
+/// Stop processing transaction replaced on chain by another one with the same nonce,
+/// its transfers are returned to the queue
+pub async fn mark_tx_replaced(
+    conn: &SqlitePool,
+    tx_id: i64,
+    replaced_by: Option<&str>,
+    actor: &str,
+    reason: &str,
+) -> Result<TxDao, PaymentError> {
+    check_actor_and_reason(actor, reason)?;
+    let mut tx = get_processing_transaction(conn, tx_id).await?;
+    let result = match replaced_by {
+        Some(replaced_by) => format!("Replaced by {replaced_by}"),
+        None => "Replaced".to_string(),
+    };
+    if is_transfer_method(&tx.method) {
+        return_transfers_to_queue(conn, &mut tx, &result).await?;
+    } else {
+        update_result_by_method(
+            conn,
+            &mut tx,
+            &ProcessTransactionResult::InternalError(result.clone()),
+        )
+        .await?;
+    }
+    record_applied_operator_action(
+        conn,
+        Some(tx_id),
+        None,
+        OPERATOR_ACTION_MARK_REPLACED,
+        actor,
+        reason,
+        &result,
+    )
+    .await?;
+    Ok(tx)
+}
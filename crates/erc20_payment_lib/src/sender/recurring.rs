@@ -0,0 +1,244 @@
+use std::str::FromStr;
+
+use crate::db::model::*;
+use crate::db::ops::*;
+use crate::error::{CustomError, ErrorBag, PaymentError};
+use crate::transaction::create_token_transfer;
+use crate::{err_custom_create, err_from};
+
+use chrono::{DateTime, Duration, Utc};
+use cron::Schedule;
+use sqlx::SqlitePool;
+use web3::types::{Address, U256};
+
+/// Limit of occurrences materialized at once for one recurring payment (i.e. after long downtime)
+const MAX_OCCURRENCES_PER_RUN: usize = 100;
+
+/// Payment id of the given occurrence, the same occurrence always gets the same id
+pub fn recurring_payment_id(recurring_payment_id: i64, occurrence: DateTime<Utc>) -> String {
+    format!(
+        "recurring-{}-{}",
+        recurring_payment_id,
+        occurrence.timestamp()
+    )
+}
+
+#[allow(clippy::too_many_arguments)]
+pub fn create_recurring_payment(
+    from: Address,
+    receiver: Address,
+    chain_id: i64,
+    token_addr: Option<Address>,
+    token_amount: U256,
+    schedule: Option<&str>,
+    interval_secs: Option<u64>,
+    start_date: Option<DateTime<Utc>>,
+    end_date: Option<DateTime<Utc>>,
+) -> Result<RecurringPaymentDao, PaymentError> {
+    match (schedule, interval_secs) {
+        (Some(schedule), None) => {
+            Schedule::from_str(schedule)
+                .map_err(|err| err_custom_create!("Invalid cron schedule {}: {}", schedule, err))?;
+        }
+        (None, Some(interval_secs)) => {
+            if interval_secs == 0 {
+                return Err(err_custom_create!("Interval has to be greater than zero"));
+            }
+        }
+        _ => {
+            return Err(err_custom_create!(
+                "Exactly one of schedule or interval has to be given"
+            ));
+        }
+    }
+    let now = Utc::now();
+    let start_date = start_date.unwrap_or(now);
+    if let Some(end_date) = end_date {
+        if end_date <= start_date {
+            return Err(err_custom_create!("End date has to be after start date"));
+        }
+    }
+    Ok(RecurringPaymentDao {
+        id: 0,
+        from_addr: format!("{from:#x}"),
+        receiver_addr: format!("{receiver:#x}"),
+        chain_id,
+        token_addr: token_addr.map(|addr| format!("{addr:#x}")),
+        token_amount: token_amount.to_string(),
+        schedule: schedule.map(|s| s.to_string()),
+        interval_secs: interval_secs.map(|i| i as i64),
+        start_date,
+        end_date,
+        last_occurrence: None,
+        active: true,
+        created_date: now,
+    })
+}
+
+/// Occurrences after the last materialized one, up to the given date
+pub fn get_due_occurrences(
+    recurring_payment: &RecurringPaymentDao,
+    until: DateTime<Utc>,
+) -> Result<Vec<DateTime<Utc>>, PaymentError> {
+    let until = match recurring_payment.end_date {
+        Some(end_date) if end_date < until => end_date,
+        _ => until,
+    };
+    let mut occurrences = Vec::new();
+    if let Some(schedule) = &recurring_payment.schedule {
+        let schedule = Schedule::from_str(schedule)
+            .map_err(|err| err_custom_create!("Invalid cron schedule {}: {}", schedule, err))?;
+        //cron returns dates strictly after the given one, start date is inclusive
+        let after = recurring_payment
+            .last_occurrence
+            .unwrap_or(recurring_payment.start_date - Duration::seconds(1));
+        occurrences.extend(
+            schedule
+                .after(&after)
+                .take_while(|occurrence| *occurrence <= until)
+                .take(MAX_OCCURRENCES_PER_RUN),
+        );
+    } else if let Some(interval_secs) = recurring_payment.interval_secs {
+        let interval = Duration::seconds(interval_secs);
+        let mut next = match recurring_payment.last_occurrence {
+            Some(last_occurrence) => last_occurrence + interval,
+            None => recurring_payment.start_date,
+        };
+        while next <= until && occurrences.len() < MAX_OCCURRENCES_PER_RUN {
+            occurrences.push(next);
+            next += interval;
+        }
+    }
+    Ok(occurrences)
+}
+
+/// Create token transfers for all due occurrences of active recurring payments
+pub async fn materialize_recurring_payments(conn: &SqlitePool) -> Result<u32, PaymentError> {
+    let now = Utc::now();
+    let mut created_count = 0;
+    let recurring_payments = get_active_recurring_payments(conn)
+        .await
+        .map_err(err_from!())?;
+    for mut recurring_payment in recurring_payments {
+        let occurrences = match get_due_occurrences(&recurring_payment, now) {
+            Ok(occurrences) => occurrences,
+            Err(err) => {
+                log::error!(
+                    "Failed to compute occurrences of recurring payment {}: {}",
+                    recurring_payment.id,
+                    err
+                );
+                continue;
+            }
+        };
+        //more occurrences could be due, if limit per run was reached
+        let all_due_created = occurrences.len() < MAX_OCCURRENCES_PER_RUN;
+        for occurrence in occurrences {
+            let payment_id = recurring_payment_id(recurring_payment.id, occurrence);
+            //transfer could be inserted already, if engine stopped before last_occurrence was saved
+            if get_token_transfer_by_payment_id(conn, &payment_id)
+                .await
+                .map_err(err_from!())?
+                .is_none()
+            {
+                let token_transfer = create_token_transfer(
+                    Address::from_str(&recurring_payment.from_addr).map_err(err_from!())?,
+                    Address::from_str(&recurring_payment.receiver_addr).map_err(err_from!())?,
+                    recurring_payment.chain_id,
+                    Some(&payment_id),
+                    recurring_payment
+                        .token_addr
+                        .as_ref()
+                        .map(|addr| Address::from_str(addr))
+                        .transpose()
+                        .map_err(err_from!())?,
+                    U256::from_dec_str(&recurring_payment.token_amount).map_err(err_from!())?,
+                );
+                insert_token_transfer(conn, &token_transfer)
+                    .await
+                    .map_err(err_from!())?;
+                log::info!(
+                    "Created transfer {} for recurring payment {}",
+                    payment_id,
+                    recurring_payment.id
+                );
+                created_count += 1;
+            }
+            recurring_payment.last_occurrence = Some(occurrence);
+            update_recurring_payment(conn, &recurring_payment)
+                .await
+                .map_err(err_from!())?;
+        }
+        if let Some(end_date) = recurring_payment.end_date {
+            if end_date <= now && all_due_created {
+                log::info!("Recurring payment {} finished", recurring_payment.id);
+                recurring_payment.active = false;
+                update_recurring_payment(conn, &recurring_payment)
+                    .await
+                    .map_err(err_from!())?;
+            }
+        }
+    }
+    Ok(created_count)
+}
+
+pub async fn cancel_recurring_payment(
+    conn: &SqlitePool,
+    id: i64,
+) -> Result<RecurringPaymentDao, PaymentError> {
+    let mut recurring_payment = get_recurring_payment(conn, id)
+        .await
+        .map_err(err_from!())?
+        .ok_or_else(|| err_custom_create!("Recurring payment {} not found", id))?;
+    recurring_payment.active = false;
+    update_recurring_payment(conn, &recurring_payment)
+        .await
+        .map_err(err_from!())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_due_occurrences() {
+        let start_date = DateTime::parse_from_rfc3339("2023-01-01T00:00:00Z")
+            .unwrap()
+            .with_timezone(&Utc);
+        let until = DateTime::parse_from_rfc3339("2023-03-15T00:00:00Z")
+            .unwrap()
+            .with_timezone(&Utc);
+        let mut monthly = create_recurring_payment(
+            Address::from_low_u64_be(1),
+            Address::from_low_u64_be(2),
+            987789,
+            None,
+            U256::from(1),
+            Some("0 0 0 1 * *"),
+            None,
+            Some(start_date),
+            None,
+        )
+        .unwrap();
+        let occurrences = get_due_occurrences(&monthly, until).unwrap();
+        assert_eq!(occurrences.len(), 3);
+        assert_eq!(occurrences[0], start_date);
+
+        monthly.last_occurrence = occurrences.last().cloned();
+        assert!(get_due_occurrences(&monthly, until).unwrap().is_empty());
+
+        let daily = create_recurring_payment(
+            Address::from_low_u64_be(1),
+            Address::from_low_u64_be(2),
+            987789,
+            None,
+            U256::from(1),
+            None,
+            Some(86400),
+            Some(start_date),
+            Some(start_date + Duration::days(10)),
+        )
+        .unwrap();
+        assert_eq!(get_due_occurrences(&daily, until).unwrap().len(), 11);
+    }
+}
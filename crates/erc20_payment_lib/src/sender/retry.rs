@@ -0,0 +1,215 @@
+use crate::db::model::*;
+use crate::db::ops::*;
+use crate::error::{CustomError, ErrorBag, PaymentError};
+use crate::setup::RetryPolicy;
+use crate::{err_custom_create, err_from};
+
+use chrono::{DateTime, Duration, Utc};
+use serde::Serialize;
+use sqlx::SqlitePool;
+
+#[derive(Serialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub enum TransferErrorClass {
+    /// Failed before the transaction was sent, retrying may succeed
+    Transient,
+    /// Retrying will fail the same way or may pay the receiver twice
+    Permanent,
+}
+
+/// Errors set before the transaction is sent, which may go away by retrying.
+/// Any other error is treated as permanent, because the transaction could have been
+/// sent and still be confirmed (for example "No receipt" after the nonce was used)
+/// Prefix of the error set on transfers that failed while gathering, before any transaction was sent
+pub const GATHER_ERROR_PREFIX: &str = "Error in gathering transactions";
+
+const TRANSIENT_ERRORS: &[&str] = &[
+    "Failed to get provider for chain id",
+    "Sign won't be possible for given address",
+    "Web3 RPC endpoint failing",
+    "Gas estimation failed",
+    "insufficient funds",
+];
+
+pub fn classify_transfer_error(error: &str) -> TransferErrorClass {
+    if error.starts_with(GATHER_ERROR_PREFIX) || TRANSIENT_ERRORS.iter().any(|e| error.contains(e))
+    {
+        TransferErrorClass::Transient
+    } else {
+        TransferErrorClass::Permanent
+    }
+}
+
+/// Transaction of the transfer was sent, so it may still be confirmed and cannot be retried
+async fn is_transfer_tx_sent(
+    conn: &SqlitePool,
+    token_transfer: &TokenTransferDao,
+) -> Result<bool, PaymentError> {
+    match token_transfer.tx_id {
+        Some(tx_id) => {
+            let tx = get_transaction(conn, tx_id).await.map_err(err_from!())?;
+            Ok(tx.broadcast_date.is_some() || tx.tx_hash.is_some())
+        }
+        None => Ok(false),
+    }
+}
+
+/// Delay before given retry attempt (attempt 2 is the first retry)
+pub fn get_retry_delay(retry_policy: &RetryPolicy, attempt: i64) -> Duration {
+    let exponent = (attempt - 2).max(0) as i32;
+    let delay = retry_policy.backoff as f64 * retry_policy.backoff_multiplier.powi(exponent);
+    Duration::seconds(delay.min(i64::MAX as f64 / 1000.0) as i64)
+}
+
+fn create_retry_attempt(
+    token_transfer: &TokenTransferDao,
+    execute_after: Option<DateTime<Utc>>,
+) -> TokenTransferDao {
+    TokenTransferDao {
+        id: 0,
+        tx_id: None,
+        fee_paid: None,
+        error: None,
+        created_date: Some(Utc::now()),
+        execute_after,
+        //approval is not requested again for approved transfer
+        approval_state: token_transfer
+            .approval_state
+            .clone()
+            .filter(|state| state == APPROVAL_STATE_APPROVED),
+        retry_of: Some(token_transfer.id),
+        attempt: token_transfer.attempt + 1,
+        dead_letter: false,
+        waiting_for_funds: false,
+        ..token_transfer.clone()
+    }
+}
+
+/// Create new attempts of failed transfers according to retry policy,
+/// permanently failed transfers and the ones out of attempts are moved to dead letter
+pub async fn retry_failed_transfers(
+    conn: &SqlitePool,
+    retry_policy: &RetryPolicy,
+) -> Result<u32, PaymentError> {
+    let mut retried_count = 0;
+    for mut token_transfer in get_token_transfers_to_retry(conn)
+        .await
+        .map_err(err_from!())?
+    {
+        let error = token_transfer.error.clone().unwrap_or_default();
+        let error_class = if is_transfer_tx_sent(conn, &token_transfer).await? {
+            TransferErrorClass::Permanent
+        } else {
+            classify_transfer_error(&error)
+        };
+        if error_class == TransferErrorClass::Permanent
+            || token_transfer.attempt >= retry_policy.max_attempts as i64
+        {
+            log::warn!(
+                "Transfer {} moved to dead letter after {} attempts ({:?} error: {})",
+                token_transfer.id,
+                token_transfer.attempt,
+                error_class,
+                error
+            );
+            token_transfer.dead_letter = true;
+            update_token_transfer(conn, &token_transfer)
+                .await
+                .map_err(err_from!())?;
+            continue;
+        }
+        let delay = get_retry_delay(retry_policy, token_transfer.attempt + 1);
+        let retry = insert_token_transfer(
+            conn,
+            &create_retry_attempt(&token_transfer, Some(Utc::now() + delay)),
+        )
+        .await
+        .map_err(err_from!())?;
+        log::info!(
+            "Transfer {} failed ({}), retrying as transfer {} (attempt {}) in {} seconds",
+            token_transfer.id,
+            error,
+            retry.id,
+            retry.attempt,
+            delay.num_seconds()
+        );
+        retried_count += 1;
+    }
+    Ok(retried_count)
+}
+
+/// Create new attempt of failed transfer right away, regardless of retry policy
+pub async fn retry_transfer(conn: &SqlitePool, id: i64) -> Result<TokenTransferDao, PaymentError> {
+    let token_transfer = get_token_transfer(conn, id)
+        .await
+        .map_err(err_from!())?
+        .ok_or_else(|| err_custom_create!("Transfer {} not found", id))?;
+    if token_transfer.error.is_none() {
+        return Err(err_custom_create!("Transfer {} has not failed", id));
+    }
+    if let Some(tx_id) = token_transfer.tx_id {
+        let tx = get_transaction(conn, tx_id).await.map_err(err_from!())?;
+        if tx.processing > 0 {
+            return Err(err_custom_create!(
+                "Transaction {} of transfer {} is still processing",
+                tx_id,
+                id
+            ));
+        }
+    }
+    if is_transfer_tx_sent(conn, &token_transfer).await? {
+        return Err(err_custom_create!(
+            "Transaction of transfer {} was already sent, it cannot be retried",
+            id
+        ));
+    }
+    if let Some(retry) = get_token_transfer_retry(conn, id)
+        .await
+        .map_err(err_from!())?
+    {
+        return Err(err_custom_create!(
+            "Transfer {} was already retried as transfer {}",
+            id,
+            retry.id
+        ));
+    }
+    let retry = insert_token_transfer(conn, &create_retry_attempt(&token_transfer, None))
+        .await
+        .map_err(err_from!())?;
+    log::info!("Transfer {} manually retried as transfer {}", id, retry.id);
+    Ok(retry)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_retry_policy() {
+        assert_eq!(
+            classify_transfer_error(TRANSFER_ERROR_DEADLINE_EXCEEDED),
+            TransferErrorClass::Permanent
+        );
+        assert_eq!(
+            classify_transfer_error("Gas estimation failed - probably insufficient funds"),
+            TransferErrorClass::Transient
+        );
+        assert_eq!(
+            classify_transfer_error(&format!(
+                "{GATHER_ERROR_PREFIX}: CustomError: Invalid chain id"
+            )),
+            TransferErrorClass::Transient
+        );
+        assert_eq!(
+            classify_transfer_error("No receipt"),
+            TransferErrorClass::Permanent
+        );
+        let retry_policy = RetryPolicy {
+            max_attempts: 4,
+            backoff: 60,
+            backoff_multiplier: 2.0,
+        };
+        assert_eq!(get_retry_delay(&retry_policy, 2).num_seconds(), 60);
+        assert_eq!(get_retry_delay(&retry_policy, 4).num_seconds(), 240);
+    }
+}
@@ -4,16 +4,22 @@ use crate::error::{ErrorBag, PaymentError};
 use std::sync::Arc;
 use tokio::sync::Mutex;
 
-use crate::sender::process::{process_transaction, ProcessTransactionResult};
+use crate::sender::process::{
+    get_multi_receivers_count, process_transaction, ProcessTransactionResult,
+};
 
 use crate::utils::ConversionError;
 
 use crate::err_from;
 use crate::setup::PaymentSetup;
 
-use crate::runtime::SharedState;
+use crate::events::PaymentEvent;
+use crate::runtime::{SharedState, ShutdownToken};
 use crate::sender::batching::{gather_transactions_post, gather_transactions_pre};
-use crate::sender::process_allowance;
+use crate::sender::{
+    apply_operator_actions, materialize_recurring_payments, process_allowance,
+    retry_failed_transfers,
+};
 use crate::signer::{PrivateKeySigner, Signer};
 use sqlx::SqlitePool;
 use web3::types::U256;
@@ -32,12 +38,16 @@ pub async fn update_token_transfer_result(
                 .await
                 .map_err(err_from!())?;
             let token_transfers_count = U256::from(token_transfers.len() as u64);
-            for mut token_transfer in token_transfers {
+            for (idx, mut token_transfer) in token_transfers.into_iter().enumerate() {
                 if let Some(fee_paid) = tx.fee_paid.clone() {
                     let val = U256::from_dec_str(&fee_paid)
                         .map_err(|_err| ConversionError::from("failed to parse fee paid".into()))
                         .map_err(err_from!())?;
-                    let val2 = val / token_transfers_count;
+                    let mut val2 = val / token_transfers_count;
+                    //first transfer takes the rest, so that fees sum up to the tx fee
+                    if idx == 0 {
+                        val2 += val % token_transfers_count;
+                    }
                     token_transfer.fee_paid = Some(val2.to_string());
                 } else {
                     token_transfer.fee_paid = None;
@@ -101,6 +111,59 @@ pub async fn update_token_transfer_result(
     Ok(())
 }
 
+pub fn is_transfer_method(method: &str) -> bool {
+    method.starts_with("MULTI.golemTransfer")
+        || method == "DISPERSE.disperseEther"
+        || method == "META.executeMetaTransaction"
+        || method == "ERC20.transfer"
+        || method == "transfer"
+}
+
+/// Update transaction and its transfers or allowance according to transaction method
+pub async fn update_result_by_method(
+    conn: &SqlitePool,
+    tx: &mut TxDao,
+    process_t_res: &ProcessTransactionResult,
+) -> Result<(), PaymentError> {
+    if is_transfer_method(&tx.method) {
+        log::debug!("Updating token transfer result");
+        update_token_transfer_result(conn, tx, process_t_res).await
+    } else if tx.method == "ERC20.approve" || tx.method == "ERC20.permit" {
+        log::debug!("Updating token approve result");
+        update_approve_result(conn, tx, process_t_res).await
+    } else {
+        log::debug!("Updating plain tx result");
+        update_tx_result(conn, tx, process_t_res).await
+    }
+}
+
+/// Detach token transfers from not sent transaction and mark it as failed,
+/// transfers are picked up again on the next gathering
+pub async fn return_transfers_to_queue(
+    conn: &SqlitePool,
+    tx: &mut TxDao,
+    reason: &str,
+) -> Result<(), PaymentError> {
+    tx.processing = 0;
+    tx.error = Some(format!("Transfers returned to queue: {reason}"));
+
+    let mut db_transaction = conn.begin().await.map_err(err_from!())?;
+    let token_transfers = get_token_transfers_by_tx(&mut db_transaction, tx.id)
+        .await
+        .map_err(err_from!())?;
+    for mut token_transfer in token_transfers {
+        token_transfer.tx_id = None;
+        update_token_transfer(&mut db_transaction, &token_transfer)
+            .await
+            .map_err(err_from!())?;
+    }
+    update_tx(&mut db_transaction, tx)
+        .await
+        .map_err(err_from!())?;
+    db_transaction.commit().await.map_err(err_from!())?;
+    Ok(())
+}
+
 pub async fn update_approve_result(
     conn: &SqlitePool,
     tx: &mut TxDao,
@@ -198,18 +261,21 @@ pub async fn process_transactions(
     conn: &SqlitePool,
     payment_setup: &PaymentSetup,
     signer: &impl Signer,
+    shutdown: &ShutdownToken,
 ) -> Result<(), PaymentError> {
     //remove tx from current processing infos
 
     loop {
+        if shutdown.is_shutdown() || shared_state.lock().await.paused {
+            break;
+        }
+        apply_operator_actions(&shared_state, conn).await?;
         let mut transactions = get_next_transactions_to_process(conn, 1)
             .await
             .map_err(err_from!())?;
 
         if let Some(tx) = transactions.get_mut(0) {
-            let process_t_res = if shared_state.lock().await.is_skipped(tx.id) {
-                ProcessTransactionResult::InternalError("Transaction skipped by user".into())
-            } else {
+            let process_t_res = {
                 shared_state
                     .lock()
                     .await
@@ -220,6 +286,7 @@ pub async fn process_transactions(
                     tx,
                     payment_setup,
                     signer,
+                    shutdown,
                     false,
                 )
                 .await
@@ -233,6 +300,24 @@ pub async fn process_transactions(
                                 .set_tx_error(tx.id, Some(err.message.clone()));
                             ProcessTransactionResult::InternalError(format!("{}", &err))
                         }
+                        //batch too big to pass gas estimation, return transfers to queue,
+                        //so they are gathered again in smaller batches
+                        ErrorBag::GasEstimationError(_)
+                            if get_multi_receivers_count(tx).unwrap_or(0) > 1 =>
+                        {
+                            let receivers = get_multi_receivers_count(tx).unwrap_or(0);
+                            log::warn!(
+                                "Estimation of batch tx {} with {} receivers failed, splitting: {}",
+                                tx.id,
+                                receivers,
+                                err
+                            );
+                            return_transfers_to_queue(conn, tx, &err.inner.to_string()).await?;
+                            let mut shared_state = shared_state.lock().await;
+                            shared_state.record_batch_failed(tx.chain_id, receivers);
+                            shared_state.current_tx_info.remove(&tx.id);
+                            continue;
+                        }
                         _ => {
                             shared_state
                                 .lock()
@@ -243,18 +328,24 @@ pub async fn process_transactions(
                     },
                 }
             };
-            if tx.method.starts_with("MULTI.golemTransfer")
-                || tx.method == "ERC20.transfer"
-                || tx.method == "transfer"
-            {
-                log::debug!("Updating token transfer result");
-                update_token_transfer_result(conn, tx, &process_t_res).await?;
-            } else if tx.method == "ERC20.approve" {
-                log::debug!("Updating token approve result");
-                update_approve_result(conn, tx, &process_t_res).await?;
-            } else {
-                log::debug!("Updating plain tx result");
-                update_tx_result(conn, tx, &process_t_res).await?;
+            update_result_by_method(conn, tx, &process_t_res).await?;
+            let event = match &process_t_res {
+                ProcessTransactionResult::Confirmed => Some(PaymentEvent::TxConfirmed {
+                    tx_id: tx.id,
+                    chain_id: tx.chain_id,
+                    tx_hash: tx.tx_hash.clone(),
+                    fee_paid: tx.fee_paid.clone(),
+                }),
+                ProcessTransactionResult::NeedRetry(error)
+                | ProcessTransactionResult::InternalError(error) => Some(PaymentEvent::TxFailed {
+                    tx_id: tx.id,
+                    chain_id: tx.chain_id,
+                    error: error.clone(),
+                }),
+                ProcessTransactionResult::Unknown => None,
+            };
+            if let Some(event) = event {
+                shared_state.lock().await.emit(event);
             }
             match process_t_res {
                 ProcessTransactionResult::Unknown => {}
@@ -266,7 +357,9 @@ pub async fn process_transactions(
         if transactions.is_empty() {
             break;
         }
-        tokio::time::sleep(std::time::Duration::from_secs(payment_setup.service_sleep)).await;
+        shutdown
+            .sleep(std::time::Duration::from_secs(payment_setup.service_sleep))
+            .await;
     }
     Ok(())
 }
@@ -275,9 +368,10 @@ pub async fn service_loop(
     shared_state: Arc<Mutex<SharedState>>,
     conn: &SqlitePool,
     payment_setup: &PaymentSetup,
+    shutdown: ShutdownToken,
 ) {
     let process_transactions_interval = 5;
-    let gather_transactions_interval = 20;
+    let gather_transactions_interval = payment_setup.gather_interval as i64;
     let mut last_update_time1 =
         chrono::Utc::now() - chrono::Duration::seconds(process_transactions_interval);
     let mut last_update_time2 =
@@ -287,6 +381,29 @@ pub async fn service_loop(
     let mut process_tx_instantly = true;
     let signer = PrivateKeySigner::new(payment_setup.secret_keys.clone());
     loop {
+        if shutdown.is_shutdown() {
+            log::info!("Shutdown requested, exiting sender service loop");
+            break;
+        }
+        match apply_operator_actions(&shared_state, conn).await {
+            Ok(0) => {}
+            Ok(_) => {
+                //released or retried work has to be picked up
+                process_tx_needed = true;
+            }
+            Err(e) => {
+                log::error!("Error in applying operator actions: {}", e);
+            }
+        }
+        if shared_state.lock().await.paused {
+            log::debug!("Sender service loop - paused");
+            //processing could be interrupted by pause, check transactions after resume
+            process_tx_needed = true;
+            shutdown
+                .sleep(std::time::Duration::from_secs(payment_setup.service_sleep))
+                .await;
+            continue;
+        }
         log::debug!("Sender service loop - start loop");
         let current_time = chrono::Utc::now();
         if current_time < last_update_time1 {
@@ -304,7 +421,14 @@ pub async fn service_loop(
                 log::warn!("Skipping processing transactions...");
                 process_tx_needed = false;
             } else {
-                match process_transactions(shared_state.clone(), conn, payment_setup, &signer).await
+                match process_transactions(
+                    shared_state.clone(),
+                    conn,
+                    payment_setup,
+                    &signer,
+                    &shutdown,
+                )
+                .await
                 {
                     Ok(_) => {
                         //all pending transactions processed
@@ -318,22 +442,54 @@ pub async fn service_loop(
             last_update_time1 = current_time;
         }
 
-        if current_time
-            > last_update_time2 + chrono::Duration::seconds(gather_transactions_interval)
+        let force_flush = shared_state.lock().await.force_flush;
+        if !shutdown.is_shutdown()
+            && (force_flush
+                || current_time
+                    > last_update_time2 + chrono::Duration::seconds(gather_transactions_interval))
             && !process_tx_needed
         {
+            if let Err(e) = materialize_recurring_payments(conn).await {
+                log::error!("Error in creating recurring payments: {}", e);
+            }
+            if let Some(retry_policy) = payment_setup.retry_policy.as_ref() {
+                if let Err(e) = retry_failed_transfers(conn, retry_policy).await {
+                    log::error!("Error in retrying failed transfers: {}", e);
+                }
+            }
             log::info!("Gathering transfers...");
-            let mut token_transfer_map = match gather_transactions_pre(conn, payment_setup).await {
-                Ok(token_transfer_map) => token_transfer_map,
+            let mut token_transfer_map = match gather_transactions_pre(
+                shared_state.clone(),
+                conn,
+                payment_setup,
+                force_flush,
+            )
+            .await
+            {
+                Ok(token_transfer_map) => {
+                    if force_flush {
+                        log::info!("Force flush - all pending transfers gathered");
+                        shared_state.lock().await.force_flush = false;
+                    }
+                    token_transfer_map
+                }
                 Err(e) => {
                     log::error!("Error in gather transactions, driver will be stuck, Fix DB to continue {:?}", e);
-                    tokio::time::sleep(std::time::Duration::from_secs(payment_setup.service_sleep))
+                    shutdown
+                        .sleep(std::time::Duration::from_secs(payment_setup.service_sleep))
                         .await;
                     continue;
                 }
             };
 
-            match gather_transactions_post(conn, payment_setup, &mut token_transfer_map).await {
+            match gather_transactions_post(
+                shared_state.clone(),
+                conn,
+                payment_setup,
+                &mut token_transfer_map,
+            )
+            .await
+            {
                 Ok(count) => {
                     if count > 0 {
                         process_tx_needed = true;
@@ -345,6 +501,16 @@ pub async fn service_loop(
                     match &e.inner {
                         ErrorBag::NoAllowanceFound(allowance_request) => {
                             log::info!("No allowance found for contract {} to spend token {} for owner: {}", allowance_request.spender_addr, allowance_request.token_addr, allowance_request.owner);
+                            shared_state
+                                .lock()
+                                .await
+                                .emit(PaymentEvent::AllowanceRequested {
+                                    chain_id: allowance_request.chain_id,
+                                    owner: allowance_request.owner.clone(),
+                                    token_addr: allowance_request.token_addr.clone(),
+                                    spender_addr: allowance_request.spender_addr.clone(),
+                                    amount: allowance_request.amount,
+                                });
                             match process_allowance(conn, payment_setup, allowance_request).await {
                                 Ok(_) => {
                                     //process transaction instantly
@@ -380,6 +546,8 @@ pub async fn service_loop(
             }
         }
 
-        tokio::time::sleep(std::time::Duration::from_secs(payment_setup.service_sleep)).await;
+        shutdown
+            .sleep(std::time::Duration::from_secs(payment_setup.service_sleep))
+            .await;
     }
 }
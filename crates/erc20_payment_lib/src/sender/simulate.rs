@@ -0,0 +1,190 @@
+use std::collections::BTreeMap;
+
+use crate::db::ops::*;
+use crate::error::{CustomError, ErrorBag, PaymentError};
+use crate::setup::PaymentSetup;
+use crate::transaction::check_transaction;
+use crate::utils::u256_to_rust_dec;
+use crate::{err_custom_create, err_from};
+
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use sqlx::SqlitePool;
+use web3::types::U256;
+
+#[derive(Serialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub enum SimulationStatus {
+    Success,
+    Revert,
+    /// Transaction needs signed payload (meta transaction, permit) to be simulated
+    Skipped,
+}
+
+#[derive(Serialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct SimulationResult {
+    pub tx_id: i64,
+    pub chain_id: i64,
+    pub method: String,
+    pub from_addr: String,
+    pub to_addr: String,
+    pub transfer_count: usize,
+    pub status: SimulationStatus,
+    pub gas_limit: Option<i64>,
+    pub gas_price: Option<U256>,
+    pub estimated_fee: Option<U256>,
+    pub error: Option<String>,
+}
+
+#[derive(Serialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct SimulationReport {
+    pub created_date: DateTime<Utc>,
+    pub results: Vec<SimulationResult>,
+    pub revert_count: usize,
+    /// Sum of estimated fees per chain id
+    pub total_estimated_fee: BTreeMap<i64, U256>,
+}
+
+/// Run eth_call and gas estimation for every queued transaction stub,
+/// nothing is signed, sent or saved to the database
+pub async fn simulate_transactions(
+    conn: &SqlitePool,
+    payment_setup: &PaymentSetup,
+) -> Result<SimulationReport, PaymentError> {
+    let txs = get_transactions(
+        conn,
+        Some(TRANSACTION_FILTER_QUEUED),
+        None,
+        Some(TRANSACTION_ORDER_BY_CREATE_DATE),
+    )
+    .await
+    .map_err(err_from!())?;
+
+    let mut gas_prices = BTreeMap::<i64, U256>::new();
+    let mut results = Vec::with_capacity(txs.len());
+    for tx in txs {
+        let web3 = payment_setup.get_provider(tx.chain_id)?;
+        let transfer_count = get_token_transfers_by_tx(conn, tx.id)
+            .await
+            .map_err(err_from!())?
+            .len();
+        let mut result = SimulationResult {
+            tx_id: tx.id,
+            chain_id: tx.chain_id,
+            method: tx.method.clone(),
+            from_addr: tx.from_addr.clone(),
+            to_addr: tx.to_addr.clone(),
+            transfer_count,
+            status: SimulationStatus::Success,
+            gas_limit: None,
+            gas_price: None,
+            estimated_fee: None,
+            error: None,
+        };
+        if tx.method == "META.executeMetaTransaction" || tx.method == "ERC20.permit" {
+            result.status = SimulationStatus::Skipped;
+            result.error = Some("Requires signature, not simulated".to_string());
+            results.push(result);
+            continue;
+        }
+
+        let gas_price = match gas_prices.get(&tx.chain_id) {
+            Some(gas_price) => *gas_price,
+            None => {
+                let gas_price = web3.eth().gas_price().await.map_err(err_from!())?;
+                gas_prices.insert(tx.chain_id, gas_price);
+                gas_price
+            }
+        };
+        let max_fee_per_gas = U256::from_dec_str(&tx.max_fee_per_gas).map_err(err_from!())?;
+        let gas_price = std::cmp::min(gas_price, max_fee_per_gas);
+        result.gas_price = Some(gas_price);
+
+        let mut simulated_tx = tx.clone();
+        match check_transaction(web3, &mut simulated_tx).await {
+            Ok(()) => {
+                result.gas_limit = simulated_tx.gas_limit;
+                result.estimated_fee = simulated_tx
+                    .gas_limit
+                    .map(|gas_limit| U256::from(gas_limit) * gas_price);
+            }
+            Err(err) => {
+                result.status = SimulationStatus::Revert;
+                result.error = Some(err.to_string());
+            }
+        }
+        results.push(result);
+    }
+
+    let mut total_estimated_fee = BTreeMap::<i64, U256>::new();
+    for result in &results {
+        if let Some(estimated_fee) = result.estimated_fee {
+            *total_estimated_fee.entry(result.chain_id).or_default() += estimated_fee;
+        }
+    }
+    Ok(SimulationReport {
+        created_date: Utc::now(),
+        revert_count: results
+            .iter()
+            .filter(|r| r.status == SimulationStatus::Revert)
+            .count(),
+        results,
+        total_estimated_fee,
+    })
+}
+
+/// Human readable table of the simulation report
+pub fn format_simulation_report(
+    report: &SimulationReport,
+    payment_setup: &PaymentSetup,
+) -> Result<String, PaymentError> {
+    let fee_to_string = |fee: Option<U256>| -> Result<String, PaymentError> {
+        match fee {
+            Some(fee) => Ok(u256_to_rust_dec(fee, None)
+                .map_err(err_from!())?
+                .to_string()),
+            None => Ok("-".to_string()),
+        }
+    };
+    let mut out = format!(
+        "{:>6} {:>8} {:<28} {:>9} {:<8} {:>10} {:>22}  {}\n",
+        "tx id", "chain", "method", "transfers", "status", "gas limit", "estimated fee", "error"
+    );
+    for result in &report.results {
+        out += &format!(
+            "{:>6} {:>8} {:<28} {:>9} {:<8} {:>10} {:>22}  {}\n",
+            result.tx_id,
+            result.chain_id,
+            result.method,
+            result.transfer_count,
+            format!("{:?}", result.status),
+            result
+                .gas_limit
+                .map(|g| g.to_string())
+                .unwrap_or_else(|| "-".to_string()),
+            fee_to_string(result.estimated_fee)?,
+            result.error.as_deref().unwrap_or_default()
+        );
+    }
+    out += &format!(
+        "{} transactions, {} would revert\n",
+        report.results.len(),
+        report.revert_count
+    );
+    for (chain_id, fee) in &report.total_estimated_fee {
+        let symbol = payment_setup
+            .chain_setup
+            .get(chain_id)
+            .map(|chain_setup| chain_setup.currency_gas_symbol.as_str())
+            .ok_or_else(|| err_custom_create!("No chain setup for chain id: {}", chain_id))?;
+        out += &format!(
+            "Estimated fee on chain {}: {} {}\n",
+            chain_id,
+            fee_to_string(Some(*fee))?,
+            symbol
+        );
+    }
+    Ok(out)
+}
@@ -1,6 +1,16 @@
+use crate::balance::{get_outgoing_amounts, read_chain_balances};
+use crate::db::model::OPERATOR_ACTION_SKIP;
 use crate::db::ops::*;
 use crate::eth::get_eth_addr_from_secret;
+use crate::events::PaymentEvent;
+use crate::export::{export_transfers, ExportFormat};
 use crate::runtime::{FaucetData, SharedState};
+use crate::screening::ReceiverScreening;
+use crate::sender::{
+    approve_transfer, cancel_recurring_payment, create_recurring_payment,
+    get_transfers_pending_approval, record_operator_action, reject_transfer, retry_transfer,
+    revoke_allowance,
+};
 use crate::setup::{ChainSetup, PaymentSetup};
 use crate::transaction::create_token_transfer;
 use actix_files::NamedFile;
@@ -9,13 +19,15 @@ use actix_web::http::header::HeaderValue;
 use actix_web::http::{header, StatusCode};
 use actix_web::web::Data;
 use actix_web::{web, HttpRequest, HttpResponse, Responder, Scope};
+use chrono::{DateTime, Utc};
+use serde::Deserialize;
 use serde_json::json;
 use sqlx::SqlitePool;
 use std::collections::BTreeMap;
 use std::str::FromStr;
 use std::sync::Arc;
 use tokio::sync::Mutex;
-use web3::types::Address;
+use web3::types::{Address, U256};
 
 pub struct ServerData {
     pub shared_state: Arc<Mutex<SharedState>>,
@@ -116,6 +128,402 @@ pub async fn allowances(data: Data<Box<ServerData>>, _req: HttpRequest) -> impl
     }))
 }
 
+pub async fn allowance_revoke(data: Data<Box<ServerData>>, req: HttpRequest) -> impl Responder {
+    let chain_id = return_on_error!(i64::from_str(req.match_info().get("chain").unwrap_or("")));
+    let owner = return_on_error!(Address::from_str(
+        req.match_info().get("owner").unwrap_or("")
+    ));
+
+    let allowance = {
+        let db_conn = data.db_connection.lock().await;
+        return_on_error!(
+            revoke_allowance(&db_conn, &data.payment_setup, owner, chain_id, None, None).await
+        )
+    };
+
+    web::Json(json!({
+        "allowance": allowance,
+    }))
+}
+
+/// Returns given sender if its key is loaded, first loaded account otherwise
+fn get_sender(payment_setup: &PaymentSetup, from: Option<Address>) -> Result<Address, String> {
+    match from {
+        Some(from) => {
+            if payment_setup
+                .secret_keys
+                .iter()
+                .any(|sk| get_eth_addr_from_secret(sk) == from)
+            {
+                Ok(from)
+            } else {
+                Err(format!("No private key loaded for {from:#x}"))
+            }
+        }
+        None => payment_setup
+            .secret_keys
+            .first()
+            .map(get_eth_addr_from_secret)
+            .ok_or_else(|| "No account found".to_string()),
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TransferRequest {
+    pub chain_id: i64,
+    /// Sender, defaults to first loaded account
+    pub from: Option<Address>,
+    pub receiver: Address,
+    /// Token address, native currency transfer when not set
+    pub token_addr: Option<Address>,
+    /// Amount in wei
+    pub amount: String,
+    pub payment_id: Option<String>,
+    pub execute_after: Option<DateTime<Utc>>,
+    pub deadline: Option<DateTime<Utc>>,
+    /// Keep as separate on-chain entry, do not merge with other transfers to the receiver
+    #[serde(default)]
+    pub no_merge: bool,
+}
+
+pub async fn new_transfer(
+    data: Data<Box<ServerData>>,
+    body: web::Json<TransferRequest>,
+) -> impl Responder {
+    let body = body.into_inner();
+    return_on_error!(data.payment_setup.get_chain_setup(body.chain_id));
+    let amount = return_on_error!(U256::from_dec_str(&body.amount));
+    let from = return_on_error!(get_sender(&data.payment_setup, body.from));
+    if let (Some(execute_after), Some(deadline)) = (body.execute_after, body.deadline) {
+        if deadline <= execute_after {
+            return web::Json(json!({"error": "Deadline has to be after execute after date"}));
+        }
+    }
+
+    if let Some(screening) = data.shared_state.lock().await.screening.clone() {
+        return_on_error!(screening.check(body.receiver));
+    }
+
+    let mut tt = create_token_transfer(
+        from,
+        body.receiver,
+        body.chain_id,
+        body.payment_id.as_deref(),
+        body.token_addr,
+        amount,
+    );
+    tt.execute_after = body.execute_after;
+    tt.deadline = body.deadline;
+    tt.no_merge = body.no_merge;
+    let token_transfer = {
+        let db_conn = data.db_connection.lock().await;
+        return_on_error!(insert_token_transfer(&*db_conn, &tt).await)
+    };
+    data.shared_state
+        .lock()
+        .await
+        .emit(PaymentEvent::TransferQueued {
+            transfer_id: token_transfer.id,
+            chain_id: token_transfer.chain_id,
+            from_addr: token_transfer.from_addr.clone(),
+            receiver_addr: token_transfer.receiver_addr.clone(),
+            token_addr: token_transfer.token_addr.clone(),
+            amount,
+        });
+
+    web::Json(json!({
+        "transfer": token_transfer,
+    }))
+}
+
+pub async fn transfers_waiting(data: Data<Box<ServerData>>) -> impl Responder {
+    let transfers = {
+        let db_conn = data.db_connection.lock().await;
+        return_on_error!(
+            get_token_transfers(&db_conn, Some(TRANSFER_FILTER_WAITING_FOR_FUNDS), None).await
+        )
+    };
+
+    web::Json(json!({
+        "transfers": transfers,
+    }))
+}
+
+pub async fn balances(data: Data<Box<ServerData>>) -> impl Responder {
+    //db lock is released before balances are read from chain
+    let mut balances = {
+        let db_conn = data.db_connection.lock().await;
+        return_on_error!(get_outgoing_amounts(&db_conn, &data.payment_setup).await)
+    };
+    read_chain_balances(&data.payment_setup, &mut balances).await;
+
+    web::Json(json!({
+        "balances": balances,
+    }))
+}
+
+pub async fn transfers_dead_letter(data: Data<Box<ServerData>>) -> impl Responder {
+    let transfers = {
+        let db_conn = data.db_connection.lock().await;
+        return_on_error!(
+            get_token_transfers(&db_conn, Some(TRANSFER_FILTER_DEAD_LETTER), None).await
+        )
+    };
+
+    web::Json(json!({
+        "transfers": transfers,
+    }))
+}
+
+pub async fn transfer_retry(data: Data<Box<ServerData>>, req: HttpRequest) -> impl Responder {
+    let id = return_on_error!(i64::from_str(req.match_info().get("id").unwrap_or("")));
+    let transfer = {
+        let db_conn = data.db_connection.lock().await;
+        return_on_error!(retry_transfer(&db_conn, id).await)
+    };
+
+    web::Json(json!({
+        "transfer": transfer,
+    }))
+}
+
+pub async fn transfers_flush(data: Data<Box<ServerData>>) -> impl Responder {
+    data.shared_state.lock().await.force_flush = true;
+
+    web::Json(json!({
+        "forceFlush": true,
+    }))
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ExportRequest {
+    /// csv (default), csv-semicolon or json
+    pub format: Option<String>,
+    pub since: Option<DateTime<Utc>>,
+    pub until: Option<DateTime<Utc>>,
+    pub sender: Option<Address>,
+    /// Token address or "native" for native currency transfers
+    pub token: Option<String>,
+    /// SQL LIKE pattern
+    pub payment_id: Option<String>,
+}
+
+pub async fn export(data: Data<Box<ServerData>>, query: web::Query<ExportRequest>) -> HttpResponse {
+    let query = query.into_inner();
+    let format = match ExportFormat::from_str(query.format.as_deref().unwrap_or("csv")) {
+        Ok(format) => format,
+        Err(err) => return HttpResponse::BadRequest().json(json!({"error": err.to_string()})),
+    };
+    let native_only = query.token.as_deref() == Some("native");
+    let token_addr = match query.token.filter(|_| !native_only) {
+        Some(token) => match Address::from_str(&token) {
+            Ok(token_addr) => Some(format!("{token_addr:#x}")),
+            Err(err) => return HttpResponse::BadRequest().json(json!({"error": err.to_string()})),
+        },
+        None => None,
+    };
+    let filter = TransferExportFilter {
+        since: query.since,
+        until: query.until,
+        from_addr: query.sender.map(|sender| format!("{sender:#x}")),
+        token_addr,
+        native_only,
+        payment_id: query.payment_id,
+    };
+    let content = {
+        let db_conn = data.db_connection.lock().await;
+        export_transfers(&db_conn, &data.payment_setup, &filter, format).await
+    };
+    match content {
+        Ok(content) => HttpResponse::Ok()
+            .content_type(format.content_type())
+            .body(content),
+        Err(err) => HttpResponse::InternalServerError().json(json!({"error": err.to_string()})),
+    }
+}
+
+pub async fn screening(data: Data<Box<ServerData>>) -> impl Responder {
+    let screening = data.shared_state.lock().await.screening.clone();
+
+    web::Json(json!({
+        "screening": screening.as_deref(),
+        "addressCount": screening.as_ref().map(|s| s.addresses.len()),
+    }))
+}
+
+pub async fn screening_reload(data: Data<Box<ServerData>>) -> impl Responder {
+    let settings = match data.payment_setup.screening.as_ref() {
+        Some(settings) => settings,
+        None => return web::Json(json!({"error": "Receiver screening not configured"})),
+    };
+    let screening = {
+        let db_conn = data.db_connection.lock().await;
+        return_on_error!(
+            ReceiverScreening::load(&db_conn, settings, &data.payment_setup.receiver_accounts)
+                .await
+        )
+    };
+    let address_count = screening.addresses.len();
+    data.shared_state.lock().await.screening = Some(Arc::new(screening));
+
+    web::Json(json!({
+        "addressCount": address_count,
+    }))
+}
+
+pub async fn approvals(data: Data<Box<ServerData>>) -> impl Responder {
+    let pending_approval = {
+        let db_conn = data.db_connection.lock().await;
+        return_on_error!(get_transfers_pending_approval(&db_conn, &data.payment_setup).await)
+    };
+
+    web::Json(json!({
+        "pendingApproval": pending_approval,
+    }))
+}
+
+/// Header carrying token of the approver, see APPROVER_TOKEN_<NAME> env variables
+const APPROVER_TOKEN_HEADER: &str = "X-Approver-Token";
+
+fn get_approver_token(req: &HttpRequest) -> &str {
+    req.headers()
+        .get(APPROVER_TOKEN_HEADER)
+        .and_then(|v| v.to_str().ok())
+        .unwrap_or("")
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ApprovalDecisionRequest {
+    /// Identity of the operator making the decision
+    pub approver: String,
+    pub reason: Option<String>,
+}
+
+pub async fn approve(
+    data: Data<Box<ServerData>>,
+    req: HttpRequest,
+    body: web::Json<ApprovalDecisionRequest>,
+) -> impl Responder {
+    let id = return_on_error!(i64::from_str(req.match_info().get("id").unwrap_or("")));
+    let token_transfer = {
+        let db_conn = data.db_connection.lock().await;
+        return_on_error!(
+            approve_transfer(
+                &db_conn,
+                &data.payment_setup,
+                id,
+                &body.approver,
+                get_approver_token(&req),
+                body.reason.as_deref()
+            )
+            .await
+        )
+    };
+
+    web::Json(json!({
+        "transfer": token_transfer,
+    }))
+}
+
+pub async fn reject(
+    data: Data<Box<ServerData>>,
+    req: HttpRequest,
+    body: web::Json<ApprovalDecisionRequest>,
+) -> impl Responder {
+    let id = return_on_error!(i64::from_str(req.match_info().get("id").unwrap_or("")));
+    let token_transfer = {
+        let db_conn = data.db_connection.lock().await;
+        return_on_error!(
+            reject_transfer(
+                &db_conn,
+                &data.payment_setup,
+                id,
+                &body.approver,
+                get_approver_token(&req),
+                body.reason.as_deref()
+            )
+            .await
+        )
+    };
+
+    web::Json(json!({
+        "transfer": token_transfer,
+    }))
+}
+
+pub async fn recurring_payments(data: Data<Box<ServerData>>) -> impl Responder {
+    let recurring_payments = {
+        let db_conn = data.db_connection.lock().await;
+        return_on_error!(get_all_recurring_payments(&db_conn).await)
+    };
+
+    web::Json(json!({
+        "recurringPayments": recurring_payments,
+    }))
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RecurringPaymentRequest {
+    pub chain_id: i64,
+    /// Sender, defaults to first loaded account
+    pub from: Option<Address>,
+    pub receiver: Address,
+    /// Token address, native currency transfer when not set
+    pub token_addr: Option<Address>,
+    /// Amount in wei
+    pub amount: String,
+    /// Cron expression (with seconds field), exclusive with interval
+    pub schedule: Option<String>,
+    pub interval_secs: Option<u64>,
+    pub start_date: Option<DateTime<Utc>>,
+    pub end_date: Option<DateTime<Utc>>,
+}
+
+pub async fn new_recurring_payment(
+    data: Data<Box<ServerData>>,
+    body: web::Json<RecurringPaymentRequest>,
+) -> impl Responder {
+    let body = body.into_inner();
+    return_on_error!(data.payment_setup.get_chain_setup(body.chain_id));
+    let amount = return_on_error!(U256::from_dec_str(&body.amount));
+    let from = return_on_error!(get_sender(&data.payment_setup, body.from));
+    let recurring_payment = return_on_error!(create_recurring_payment(
+        from,
+        body.receiver,
+        body.chain_id,
+        body.token_addr,
+        amount,
+        body.schedule.as_deref(),
+        body.interval_secs,
+        body.start_date,
+        body.end_date,
+    ));
+    let recurring_payment = {
+        let db_conn = data.db_connection.lock().await;
+        return_on_error!(insert_recurring_payment(&db_conn, &recurring_payment).await)
+    };
+
+    web::Json(json!({
+        "recurringPayment": recurring_payment,
+    }))
+}
+
+pub async fn cancel_recurring(data: Data<Box<ServerData>>, req: HttpRequest) -> impl Responder {
+    let id = return_on_error!(i64::from_str(req.match_info().get("id").unwrap_or("")));
+    let recurring_payment = {
+        let db_conn = data.db_connection.lock().await;
+        return_on_error!(cancel_recurring_payment(&db_conn, id).await)
+    };
+
+    web::Json(json!({
+        "recurringPayment": recurring_payment,
+    }))
+}
+
 pub async fn transactions_count(data: Data<Box<ServerData>>, _req: HttpRequest) -> impl Responder {
     let queued_tx_count = {
         let db_conn = data.db_connection.lock().await;
@@ -180,30 +588,81 @@ pub async fn transactions(data: Data<Box<ServerData>>, _req: HttpRequest) -> imp
     }))
 }
 
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SkipRequest {
+    pub actor: Option<String>,
+    pub reason: Option<String>,
+}
+
 pub async fn skip_pending_operation(
     data: Data<Box<ServerData>>,
     req: HttpRequest,
+    query: web::Query<SkipRequest>,
 ) -> impl Responder {
-    let tx_id = req
-        .match_info()
-        .get("tx_id")
-        .map(|tx_id| i64::from_str(tx_id).ok())
-        .unwrap_or(None);
-    if let Some(tx_id) = tx_id {
-        if data.shared_state.lock().await.skip_tx(tx_id) {
-            web::Json(json!({
-                "success": "true",
-            }))
-        } else {
-            web::Json(json!({
-                "error": "Tx not found",
-            }))
-        }
-    } else {
-        web::Json(json!({
-            "error": "failed to parse tx_id",
-        }))
-    }
+    let tx_id = return_on_error!(i64::from_str(req.match_info().get("tx_id").unwrap_or("")));
+    let query = query.into_inner();
+    let operator_action = {
+        let db_conn = data.db_connection.lock().await;
+        return_on_error!(
+            record_operator_action(
+                &db_conn,
+                tx_id,
+                OPERATOR_ACTION_SKIP,
+                query.actor.as_deref().unwrap_or("api"),
+                query.reason.as_deref().unwrap_or("Skipped through API"),
+            )
+            .await
+        )
+    };
+
+    web::Json(json!({
+        "success": "true",
+        "operatorAction": operator_action,
+    }))
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct OperatorActionRequest {
+    /// skip, hold, release, retry or cancel
+    pub action: String,
+    pub actor: String,
+    pub reason: String,
+}
+
+pub async fn tx_operator_action(
+    data: Data<Box<ServerData>>,
+    req: HttpRequest,
+    body: web::Json<OperatorActionRequest>,
+) -> impl Responder {
+    let tx_id = return_on_error!(i64::from_str(req.match_info().get("tx_id").unwrap_or("")));
+    let body = body.into_inner();
+    let operator_action = {
+        let db_conn = data.db_connection.lock().await;
+        return_on_error!(
+            record_operator_action(&db_conn, tx_id, &body.action, &body.actor, &body.reason).await
+        )
+    };
+
+    web::Json(json!({
+        "operatorAction": operator_action,
+    }))
+}
+
+pub async fn operator_actions(data: Data<Box<ServerData>>, req: HttpRequest) -> impl Responder {
+    let tx_id = match req.match_info().get("tx_id") {
+        Some(tx_id) => Some(return_on_error!(i64::from_str(tx_id))),
+        None => None,
+    };
+    let operator_actions = {
+        let db_conn = data.db_connection.lock().await;
+        return_on_error!(get_operator_actions(&db_conn, tx_id, None).await)
+    };
+
+    web::Json(json!({
+        "operatorActions": operator_actions,
+    }))
 }
 
 pub async fn transactions_next(data: Data<Box<ServerData>>, req: HttpRequest) -> impl Responder {
@@ -529,6 +988,9 @@ pub async fn faucet(data: Data<Box<ServerData>>, req: HttpRequest) -> impl Respo
     let chain_id = req.match_info().get("chain").unwrap_or("");
     if !target_addr.is_empty() {
         let receiver_addr = return_on_error!(web3::types::Address::from_str(target_addr));
+        if let Some(screening) = data.shared_state.lock().await.screening.clone() {
+            return_on_error!(screening.check(receiver_addr));
+        }
 
         let chain_id = return_on_error!(i64::from_str(chain_id));
 
@@ -612,7 +1074,7 @@ pub async fn faucet(data: Data<Box<ServerData>>, req: HttpRequest) -> impl Respo
                 faucet_eth_amount,
             );
             let db_conn = data.db_connection.lock().await;
-            return_on_error!(insert_token_transfer(&db_conn, &tt).await)
+            return_on_error!(insert_token_transfer(&*db_conn, &tt).await)
         };
         let token_transfer_glm = {
             let tt = create_token_transfer(
@@ -624,7 +1086,7 @@ pub async fn faucet(data: Data<Box<ServerData>>, req: HttpRequest) -> impl Respo
                 faucet_glm_amount,
             );
             let db_conn = data.db_connection.lock().await;
-            return_on_error!(insert_token_transfer(&db_conn, &tt).await)
+            return_on_error!(insert_token_transfer(&*db_conn, &tt).await)
         };
 
         return web::Json(json!({
@@ -651,6 +1113,10 @@ pub fn runtime_web_scope(
     let mut api_scope = api_scope
         .app_data(server_data)
         .route("/allowances", web::get().to(allowances))
+        .route(
+            "/allowance/revoke/{chain}/{owner}",
+            web::post().to(allowance_revoke),
+        )
         .route("/config", web::get().to(config_endpoint))
         .route("/transactions", web::get().to(transactions))
         .route("/transactions/count", web::get().to(transactions_count))
@@ -674,9 +1140,30 @@ pub fn runtime_web_scope(
         )
         .route("/tx/skip/{tx_id}", web::post().to(skip_pending_operation))
         .route("/tx/{tx_id}", web::get().to(tx_details))
+        .route("/tx/{tx_id}/action", web::post().to(tx_operator_action))
+        .route("/tx/{tx_id}/actions", web::get().to(operator_actions))
+        .route("/actions", web::get().to(operator_actions))
         .route("/transfers", web::get().to(transfers))
+        .route("/transfers", web::post().to(new_transfer))
+        .route("/transfers/flush", web::post().to(transfers_flush))
+        .route("/transfers/waiting", web::get().to(transfers_waiting))
+        .route(
+            "/transfers/dead-letter",
+            web::get().to(transfers_dead_letter),
+        )
+        .route("/transfers/{id}/retry", web::post().to(transfer_retry))
+        .route("/export", web::get().to(export))
+        .route("/screening", web::get().to(screening))
+        .route("/screening/reload", web::post().to(screening_reload))
+        .route("/approvals", web::get().to(approvals))
+        .route("/approvals/{id}/approve", web::post().to(approve))
+        .route("/approvals/{id}/reject", web::post().to(reject))
+        .route("/recurring", web::get().to(recurring_payments))
+        .route("/recurring", web::post().to(new_recurring_payment))
+        .route("/recurring/{id}/cancel", web::post().to(cancel_recurring))
         .route("/transfers/{tx_id}", web::get().to(transfers))
         .route("/accounts", web::get().to(accounts))
+        .route("/balances", web::get().to(balances))
         .route("/account/{account}", web::get().to(account_details))
         .route("/account/{account}/in", web::get().to(account_payments_in))
         .route("/", web::get().to(greet))
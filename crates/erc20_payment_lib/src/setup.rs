@@ -1,8 +1,10 @@
-use crate::config::Config;
+use crate::config::{
+    AllowancePolicyKind, BatchingSettings, Config, ScreeningSettings, SpendingLimits,
+};
 use crate::error::PaymentError;
 use crate::error::{CustomError, ErrorBag};
 
-use crate::utils::gwei_to_u256;
+use crate::utils::{gwei_to_u256, rust_dec_to_u256};
 use crate::{err_custom_create, err_from};
 use rand::Rng;
 use secp256k1::SecretKey;
@@ -12,12 +14,111 @@ use web3::transports::Http;
 use web3::types::{Address, U256};
 use web3::Web3;
 
+/// Cached unlimited allowance is verified on chain again after this many seconds by default
+const DEFAULT_ALLOWANCE_RECHECK_INTERVAL: u64 = 3600;
+
 #[derive(Clone, Debug)]
 pub struct ProviderSetup {
     pub provider: Web3<Http>,
     pub number_of_calls: u64,
 }
 
+#[derive(Serialize, Clone, Debug, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub enum AllowancePolicy {
+    Unlimited,
+    ExactPerBatch,
+    Cap(U256),
+}
+
+#[derive(Serialize, Clone, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct BatchingPolicy {
+    pub min_batch_size: usize,
+    pub max_wait_time: Option<u64>,
+    pub flush_gas_price: Option<U256>,
+}
+
+impl BatchingPolicy {
+    fn from_config(batching: &BatchingSettings) -> Result<Self, PaymentError> {
+        Ok(BatchingPolicy {
+            min_batch_size: batching.min_batch_size.unwrap_or(1),
+            max_wait_time: batching.max_wait_time,
+            flush_gas_price: match batching.flush_gas_price {
+                Some(f) => Some(gwei_to_u256(f).map_err(err_from!())?),
+                None => None,
+            },
+        })
+    }
+}
+
+#[derive(Serialize, Clone, Debug, Default)]
+#[serde(rename_all = "camelCase")]
+pub struct SpendingLimitsSetup {
+    pub max_per_transfer: Option<U256>,
+    pub max_per_receiver_per_transaction: Option<U256>,
+    pub max_per_batch: Option<U256>,
+    pub max_per_receiver_daily: Option<U256>,
+    pub max_per_sender_daily: Option<U256>,
+}
+
+impl SpendingLimitsSetup {
+    fn from_config(limits: &SpendingLimits, decimals: u32) -> Result<Self, PaymentError> {
+        let to_u256 = |value: Option<rust_decimal::Decimal>| -> Result<Option<U256>, PaymentError> {
+            match value {
+                Some(value) => Ok(Some(
+                    rust_dec_to_u256(value, Some(decimals)).map_err(err_from!())?,
+                )),
+                None => Ok(None),
+            }
+        };
+        Ok(SpendingLimitsSetup {
+            max_per_transfer: to_u256(limits.max_per_transfer)?,
+            max_per_receiver_per_transaction: to_u256(limits.max_per_receiver_per_transaction)?,
+            max_per_batch: to_u256(limits.max_per_batch)?,
+            max_per_receiver_daily: to_u256(limits.max_per_receiver_daily)?,
+            max_per_sender_daily: to_u256(limits.max_per_sender_daily)?,
+        })
+    }
+}
+
+#[derive(Serialize, Clone, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct RetryPolicy {
+    pub max_attempts: u32,
+    pub backoff: u64,
+    pub backoff_multiplier: f64,
+}
+
+#[derive(Serialize, Clone, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct ApprovalRules {
+    /// In ether/token units, converted with decimals of the transferred currency
+    pub amount_above: Option<rust_decimal::Decimal>,
+    pub new_receiver: bool,
+    pub tokens: Vec<Address>,
+    pub required_approvers: usize,
+    pub approvers: Vec<String>,
+    /// Tokens of approvers found in env, approvers without token cannot decide
+    #[serde(skip_serializing)]
+    pub approver_tokens: BTreeMap<String, String>,
+}
+
+/// Name of env variable holding token of the approver
+pub fn approver_token_env_name(approver: &str) -> String {
+    format!(
+        "APPROVER_TOKEN_{}",
+        approver
+            .chars()
+            .map(|c| if c.is_ascii_alphanumeric() {
+                c.to_ascii_uppercase()
+            } else {
+                '_'
+            })
+            .collect::<String>()
+    )
+}
+
 #[derive(Serialize, Clone, Debug)]
 #[serde(rename_all = "camelCase")]
 pub struct ChainSetup {
@@ -31,8 +132,24 @@ pub struct ChainSetup {
     pub gas_left_warning_limit: u64,
     pub priority_fee: U256,
     pub glm_address: Option<Address>,
+    pub token_decimals: u32,
+    pub token_min_payout: Option<U256>,
+    pub native_min_payout: Option<U256>,
+    pub token_limits: Option<SpendingLimitsSetup>,
+    pub native_limits: Option<SpendingLimitsSetup>,
+    pub approval_rules: Option<ApprovalRules>,
     pub multi_contract_address: Option<Address>,
     pub multi_contract_max_at_once: usize,
+    pub multi_contract_direct: bool,
+    pub multi_contract_max_gas: Option<u64>,
+    pub native_multi_contract_address: Option<Address>,
+    pub native_multi_contract_max_at_once: usize,
+    pub meta_transaction_relayer: Option<Address>,
+    pub allowance_policy: AllowancePolicy,
+    pub allowance_recheck_interval: u64,
+    pub batching_policy: Option<BatchingPolicy>,
+    pub token_batching_policy: Option<BatchingPolicy>,
+    pub no_merge: bool,
     pub transaction_timeout: u64,
     pub skip_multi_contract_check: bool,
     pub confirmation_blocks: u64,
@@ -41,6 +158,46 @@ pub struct ChainSetup {
     pub block_explorer_url: Option<String>,
 }
 
+impl ChainSetup {
+    /// Spending limits of native currency or configured token, other tokens are not limited
+    pub fn get_spending_limits(&self, token_addr: Option<Address>) -> Option<&SpendingLimitsSetup> {
+        match token_addr {
+            None => self.native_limits.as_ref(),
+            Some(token_addr) if Some(token_addr) == self.glm_address => self.token_limits.as_ref(),
+            Some(_) => None,
+        }
+    }
+
+    /// Decimals of native currency or configured token, None for other tokens
+    pub fn get_decimals(&self, token_addr: Option<Address>) -> Option<u32> {
+        match token_addr {
+            None => Some(18),
+            Some(token_addr) if Some(token_addr) == self.glm_address => Some(self.token_decimals),
+            Some(_) => None,
+        }
+    }
+
+    /// Min payout of native currency or configured token, other tokens are sent right away
+    pub fn get_min_payout(&self, token_addr: Option<Address>) -> Option<U256> {
+        match token_addr {
+            None => self.native_min_payout,
+            Some(token_addr) if Some(token_addr) == self.glm_address => self.token_min_payout,
+            Some(_) => None,
+        }
+    }
+
+    /// Batching policy of configured token overrides the chain policy
+    pub fn get_batching_policy(&self, token_addr: Option<Address>) -> Option<&BatchingPolicy> {
+        match token_addr {
+            Some(token_addr) if Some(token_addr) == self.glm_address => self
+                .token_batching_policy
+                .as_ref()
+                .or(self.batching_policy.as_ref()),
+            _ => self.batching_policy.as_ref(),
+        }
+    }
+}
+
 #[derive(Serialize, Clone, Debug)]
 #[serde(rename_all = "camelCase")]
 pub struct PaymentSetup {
@@ -55,6 +212,9 @@ pub struct PaymentSetup {
     pub service_sleep: u64,
     pub process_sleep: u64,
     pub automatic_recover: bool,
+    pub gather_interval: u64,
+    pub screening: Option<ScreeningSettings>,
+    pub retry_policy: Option<RetryPolicy>,
 }
 
 impl PaymentSetup {
@@ -81,6 +241,13 @@ impl PaymentSetup {
             service_sleep,
             process_sleep,
             automatic_recover,
+            gather_interval: config.engine.gather_interval,
+            screening: config.screening.clone(),
+            retry_policy: config.engine.retry.as_ref().map(|retry| RetryPolicy {
+                max_attempts: retry.max_attempts,
+                backoff: retry.backoff,
+                backoff_multiplier: retry.backoff_multiplier,
+            }),
         };
         for chain_config in &config.chain {
             let mut providers = Vec::new();
@@ -107,6 +274,103 @@ impl PaymentSetup {
                 Some(f) => Some(gwei_to_u256(*f).map_err(err_from!())?),
                 None => None,
             };
+            let native_min_payout = match chain_config.1.native_min_payout {
+                Some(min_payout) => {
+                    Some(rust_dec_to_u256(min_payout, Some(18)).map_err(err_from!())?)
+                }
+                None => None,
+            };
+
+            let token_decimals = chain_config
+                .1
+                .token
+                .as_ref()
+                .and_then(|t| t.decimals)
+                .unwrap_or(18);
+            let allowance_policy = match &chain_config.1.allowance {
+                None => AllowancePolicy::Unlimited,
+                Some(allowance) => match allowance.policy {
+                    AllowancePolicyKind::Unlimited => AllowancePolicy::Unlimited,
+                    AllowancePolicyKind::ExactPerBatch => AllowancePolicy::ExactPerBatch,
+                    AllowancePolicyKind::Cap => AllowancePolicy::Cap(
+                        rust_dec_to_u256(
+                            allowance.cap.ok_or_else(|| {
+                                err_custom_create!(
+                                    "Allowance cap not set for chain {}",
+                                    chain_config.0
+                                )
+                            })?,
+                            Some(token_decimals),
+                        )
+                        .map_err(err_from!())?,
+                    ),
+                },
+            };
+
+            let token_min_payout = match chain_config.1.token.as_ref().and_then(|t| t.min_payout) {
+                Some(min_payout) => {
+                    Some(rust_dec_to_u256(min_payout, Some(token_decimals)).map_err(err_from!())?)
+                }
+                None => None,
+            };
+
+            let token_limits = match chain_config
+                .1
+                .token
+                .as_ref()
+                .and_then(|t| t.limits.as_ref())
+            {
+                Some(limits) => Some(SpendingLimitsSetup::from_config(limits, token_decimals)?),
+                None => None,
+            };
+            let native_limits = match chain_config.1.native_limits.as_ref() {
+                Some(limits) => Some(SpendingLimitsSetup::from_config(limits, 18)?),
+                None => None,
+            };
+
+            let approval_rules = chain_config
+                .1
+                .approval
+                .as_ref()
+                .map(|approval| ApprovalRules {
+                    amount_above: approval.amount_above,
+                    new_receiver: approval.new_receiver,
+                    tokens: approval.tokens.clone(),
+                    required_approvers: approval.required_approvers.max(1),
+                    approvers: approval.approvers.clone(),
+                    approver_tokens: approval
+                        .approvers
+                        .iter()
+                        .filter_map(|approver| {
+                            let env_name = approver_token_env_name(approver);
+                            match std::env::var(&env_name) {
+                                Ok(token) if !token.is_empty() => Some((approver.clone(), token)),
+                                _ => {
+                                    log::warn!(
+                                        "Approver {} has no token set in {}, cannot decide on transfers",
+                                        approver,
+                                        env_name
+                                    );
+                                    None
+                                }
+                            }
+                        })
+                        .collect(),
+                });
+
+            let batching_policy = match &chain_config.1.batching {
+                Some(batching) => Some(BatchingPolicy::from_config(batching)?),
+                None => None,
+            };
+            let token_batching_policy = match chain_config
+                .1
+                .token
+                .as_ref()
+                .and_then(|t| t.batching.as_ref())
+            {
+                Some(batching) => Some(BatchingPolicy::from_config(batching)?),
+                None => None,
+            };
 
             ps.chain_setup.insert(
                 chain_config.1.chain_id,
@@ -117,6 +381,12 @@ impl PaymentSetup {
                         .map_err(err_from!())?,
                     priority_fee: gwei_to_u256(chain_config.1.priority_fee).map_err(err_from!())?,
                     glm_address: chain_config.1.token.clone().map(|t| t.address),
+                    token_decimals,
+                    token_min_payout,
+                    native_min_payout,
+                    token_limits,
+                    native_limits,
+                    approval_rules,
                     currency_glm_symbol: chain_config
                         .1
                         .token
@@ -134,6 +404,43 @@ impl PaymentSetup {
                         .clone()
                         .map(|m| m.max_at_once)
                         .unwrap_or(1),
+                    multi_contract_direct: chain_config
+                        .1
+                        .multi_contract
+                        .as_ref()
+                        .map(|m| m.direct)
+                        .unwrap_or(false),
+                    multi_contract_max_gas: chain_config
+                        .1
+                        .multi_contract
+                        .as_ref()
+                        .and_then(|m| m.max_gas),
+                    native_multi_contract_address: chain_config
+                        .1
+                        .native_multi_contract
+                        .as_ref()
+                        .map(|m| m.address),
+                    native_multi_contract_max_at_once: chain_config
+                        .1
+                        .native_multi_contract
+                        .as_ref()
+                        .map(|m| m.max_at_once)
+                        .unwrap_or(1),
+                    meta_transaction_relayer: chain_config
+                        .1
+                        .meta_transaction
+                        .clone()
+                        .map(|m| m.relayer),
+                    allowance_policy,
+                    allowance_recheck_interval: chain_config
+                        .1
+                        .allowance
+                        .as_ref()
+                        .and_then(|a| a.recheck_interval)
+                        .unwrap_or(DEFAULT_ALLOWANCE_RECHECK_INTERVAL),
+                    batching_policy,
+                    token_batching_policy,
+                    no_merge: chain_config.1.no_merge,
                     transaction_timeout: chain_config.1.transaction_timeout,
                     skip_multi_contract_check,
                     confirmation_blocks: chain_config.1.confirmation_blocks,
@@ -2,7 +2,8 @@ use crate::contracts::DUMMY_RPC_PROVIDER;
 use crate::eth::get_eth_addr_from_secret;
 use async_trait::async_trait;
 use secp256k1::SecretKey;
-use web3::types::{SignedTransaction, TransactionParameters, H160};
+use web3::signing::{Key, SecretKeyRef, Signature};
+use web3::types::{SignedTransaction, TransactionParameters, H160, H256};
 
 #[derive(Debug)]
 pub struct SignerError {
@@ -20,6 +21,10 @@ pub trait Signer {
         pub_address: H160,
         tp: TransactionParameters,
     ) -> Result<SignedTransaction, SignerError>;
+
+    /// Sign 32 byte hash (i.e. EIP-712 typed data hash) for given public address.
+    /// Returned v value is in electrum notation (27 or 28)
+    async fn sign_hash(&self, pub_address: H160, hash: H256) -> Result<Signature, SignerError>;
 }
 
 /// PrivateKeySigner is implementation of Signer trait that stores private keys in memory and use
@@ -64,4 +69,13 @@ impl Signer for PrivateKeySigner {
             })?;
         Ok(signed)
     }
+
+    async fn sign_hash(&self, pub_address: H160, hash: H256) -> Result<Signature, SignerError> {
+        let secret_key = self.get_private_key(pub_address)?;
+        SecretKeyRef::new(secret_key)
+            .sign(hash.as_bytes(), None)
+            .map_err(|err| SignerError {
+                message: format!("Error when signing hash in PrivateKeySigner {err}"),
+            })
+    }
 }
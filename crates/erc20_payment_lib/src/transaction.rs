@@ -1,11 +1,15 @@
 use crate::contracts::*;
 use crate::db::model::*;
+use crate::eip712::{meta_transaction_struct_hash, permit_struct_hash, typed_data_hash};
 use crate::error::*;
-use crate::eth::get_eth_addr_from_secret;
+use crate::eth::{
+    get_domain_separator, get_eth_addr_from_secret, get_meta_transaction_nonce,
+    get_permit_domain_separator, get_permit_nonce,
+};
 use crate::multi::pack_transfers_for_multi_contract;
 use crate::signer::Signer;
 use crate::utils::ConversionError;
-use crate::{err_custom_create, err_from};
+use crate::{err_create, err_custom_create, err_from};
 use chrono::{DateTime, NaiveDateTime, Utc};
 use secp256k1::SecretKey;
 use std::collections::HashMap;
@@ -94,6 +98,15 @@ pub fn create_token_transfer(
         tx_id: None,
         fee_paid: None,
         error: None,
+        created_date: Some(chrono::Utc::now()),
+        execute_after: None,
+        deadline: None,
+        no_merge: false,
+        approval_state: None,
+        retry_of: None,
+        attempt: 1,
+        dead_letter: false,
+        waiting_for_funds: false,
     }
 }
 
@@ -220,6 +233,53 @@ pub fn create_erc20_transfer(
     })
 }
 
+#[allow(clippy::too_many_arguments)]
+pub fn create_eth_transfer_multi(
+    from: Address,
+    contract: Address,
+    eth_to: Vec<Address>,
+    eth_amount: Vec<U256>,
+    chain_id: u64,
+    gas_limit: Option<u64>,
+    max_fee_per_gas: U256,
+    priority_fee: U256,
+) -> Result<TxDao, PaymentError> {
+    let sum = eth_amount
+        .iter()
+        .try_fold(U256::zero(), |acc, amount| acc.checked_add(*amount))
+        .ok_or_else(|| err_custom_create!("Sum of native transfers overflows"))?;
+    let data = encode_disperse_ether(eth_to, eth_amount).map_err(err_from!())?;
+
+    Ok(TxDao {
+        id: 0,
+        method: "DISPERSE.disperseEther".to_string(),
+        from_addr: format!("{from:#x}"),
+        to_addr: format!("{contract:#x}"),
+        chain_id: chain_id as i64,
+        gas_limit: gas_limit.map(|gas_limit| gas_limit as i64),
+        max_fee_per_gas: max_fee_per_gas.to_string(),
+        priority_fee: priority_fee.to_string(),
+        val: sum.to_string(),
+        nonce: None,
+        processing: 1,
+        call_data: Some(hex::encode(data)),
+        signed_raw_data: None,
+        created_date: chrono::Utc::now(),
+        first_processed: None,
+        signed_date: None,
+        broadcast_date: None,
+        broadcast_count: 0,
+        tx_hash: None,
+        confirm_date: None,
+        block_number: None,
+        chain_status: None,
+        fee_paid: None,
+        error: None,
+        engine_message: None,
+        engine_error: None,
+    })
+}
+
 #[allow(clippy::too_many_arguments)]
 pub fn create_erc20_transfer_multi(
     from: Address,
@@ -276,10 +336,67 @@ pub fn create_erc20_transfer_multi(
     })
 }
 
+/// ERC20 transfer wrapped into executeMetaTransaction call sent by relayer.
+/// Call data contains empty signature, which is filled by sign_meta_transaction
+/// just before the transaction is signed by the relayer.
+#[allow(clippy::too_many_arguments)]
+pub fn create_erc20_transfer_meta(
+    from: Address,
+    relayer: Address,
+    token: Address,
+    erc20_to: Address,
+    erc20_amount: U256,
+    chain_id: u64,
+    gas_limit: Option<u64>,
+    max_fee_per_gas: U256,
+    priority_fee: U256,
+) -> Result<TxDao, PaymentError> {
+    let function_signature = encode_erc20_transfer(erc20_to, erc20_amount).map_err(err_from!())?;
+    Ok(TxDao {
+        id: 0,
+        method: "META.executeMetaTransaction".to_string(),
+        from_addr: format!("{relayer:#x}"),
+        to_addr: format!("{token:#x}"),
+        chain_id: chain_id as i64,
+        gas_limit: gas_limit.map(|gas_limit| gas_limit as i64),
+        max_fee_per_gas: max_fee_per_gas.to_string(),
+        priority_fee: priority_fee.to_string(),
+        val: "0".to_string(),
+        nonce: None,
+        processing: 1,
+        call_data: Some(hex::encode(
+            encode_execute_meta_transaction(
+                from,
+                function_signature,
+                H256::zero(),
+                H256::zero(),
+                0,
+            )
+            .map_err(err_from!())?,
+        )),
+        signed_raw_data: None,
+        created_date: chrono::Utc::now(),
+        first_processed: None,
+        signed_date: None,
+        broadcast_date: None,
+        broadcast_count: 0,
+        tx_hash: None,
+        confirm_date: None,
+        block_number: None,
+        chain_status: None,
+        fee_paid: None,
+        error: None,
+        engine_message: None,
+        engine_error: None,
+    })
+}
+
+#[allow(clippy::too_many_arguments)]
 pub fn create_erc20_approve(
     from: Address,
     token: Address,
     contract_to_approve: Address,
+    amount: U256,
     chain_id: u64,
     gas_limit: Option<u64>,
     max_fee_per_gas: U256,
@@ -298,7 +415,63 @@ pub fn create_erc20_approve(
         nonce: None,
         processing: 1,
         call_data: Some(hex::encode(
-            encode_erc20_approve(contract_to_approve, U256::max_value()).map_err(err_from!())?,
+            encode_erc20_approve(contract_to_approve, amount).map_err(err_from!())?,
+        )),
+        signed_raw_data: None,
+        created_date: chrono::Utc::now(),
+        first_processed: None,
+        signed_date: None,
+        broadcast_date: None,
+        broadcast_count: 0,
+        tx_hash: None,
+        confirm_date: None,
+        block_number: None,
+        chain_status: None,
+        fee_paid: None,
+        error: None,
+        engine_message: None,
+        engine_error: None,
+    })
+}
+
+/// EIP-2612 permit used instead of approve. Sender can be the owner itself or a relayer.
+/// Call data contains empty signature, which is filled by sign_permit
+/// just before the transaction is signed by the sender.
+#[allow(clippy::too_many_arguments)]
+pub fn create_erc20_permit(
+    owner: Address,
+    sender: Address,
+    token: Address,
+    contract_to_approve: Address,
+    amount: U256,
+    chain_id: u64,
+    gas_limit: Option<u64>,
+    max_fee_per_gas: U256,
+    priority_fee: U256,
+) -> Result<TxDao, PaymentError> {
+    Ok(TxDao {
+        id: 0,
+        method: "ERC20.permit".to_string(),
+        from_addr: format!("{sender:#x}"),
+        to_addr: format!("{token:#x}"),
+        chain_id: chain_id as i64,
+        gas_limit: gas_limit.map(|gas_limit| gas_limit as i64),
+        max_fee_per_gas: max_fee_per_gas.to_string(),
+        priority_fee: priority_fee.to_string(),
+        val: "0".to_string(),
+        nonce: None,
+        processing: 1,
+        call_data: Some(hex::encode(
+            encode_erc20_permit(
+                owner,
+                contract_to_approve,
+                amount,
+                U256::max_value(),
+                0,
+                H256::zero(),
+                H256::zero(),
+            )
+            .map_err(err_from!())?,
         )),
         signed_raw_data: None,
         created_date: chrono::Utc::now(),
@@ -340,16 +513,19 @@ pub async fn check_transaction(
         let gas_est = match web3.eth().estimate_gas(call_request.clone(), None).await {
             Ok(gas_est) => gas_est,
             Err(e) => {
+                //too big batch fails with the same error as insufficient funds
                 if e.to_string().contains("gas required exceeds allowance") {
-                    log::error!("Gas estimation failed - probably insufficient funds: {}", e);
-                    return Err(err_custom_create!(
-                        "Gas estimation failed - probably insufficient funds"
-                    ));
+                    log::error!(
+                        "Gas estimation failed - gas required exceeds allowance: {}",
+                        e
+                    );
+                    return Err(err_create!(GasEstimationError::new(
+                        "Gas estimation failed - gas required exceeds allowance, probably insufficient funds or too big transaction"
+                    )));
                 }
-                return Err(err_custom_create!(
-                    "Gas estimation failed due to unknown error {}",
-                    e
-                ));
+                return Err(err_create!(GasEstimationError::new(&format!(
+                    "Gas estimation failed due to unknown error {e}"
+                ))));
             }
         };
 
@@ -416,6 +592,110 @@ pub async fn sign_transaction_with_callback(
     Ok(())
 }
 
+/// Fill signature of the user into executeMetaTransaction call data.
+/// Nonce and domain separator are taken from the token contract at the time of signing.
+pub async fn sign_meta_transaction(
+    web3: &Web3<Http>,
+    web3_tx_dao: &mut TxDao,
+    signer: &impl Signer,
+) -> Result<(), PaymentError> {
+    let call_data = decode_data_to_bytes(web3_tx_dao)?
+        .ok_or_else(|| err_custom_create!("Missing call data for meta transaction"))?;
+    let (user, function_signature, sig_v) =
+        decode_execute_meta_transaction(&call_data.0).map_err(err_from!())?;
+    if sig_v != 0 {
+        log::debug!("Meta transaction {} already signed", web3_tx_dao.id);
+        return Ok(());
+    }
+    signer.check_if_sign_possible(user).await.map_err(|err| {
+        err_create!(TransactionFailedError::new(&format!(
+            "Sign of meta transaction won't be possible for given address: {user:#x}, error: {err:?}"
+        )))
+    })?;
+
+    let token = Address::from_str(&web3_tx_dao.to_addr).map_err(err_from!())?;
+    let nonce = get_meta_transaction_nonce(web3, token, user).await?;
+    let domain_separator = get_domain_separator(web3, token).await?;
+    let hash = typed_data_hash(
+        domain_separator,
+        meta_transaction_struct_hash(nonce, user, &function_signature),
+    );
+    let signature = signer.sign_hash(user, hash).await.map_err(|err| {
+        err_custom_create!("Signing meta transaction failed due to unknown error: {err:?}")
+    })?;
+    log::debug!(
+        "Meta transaction {} signed by {:#x} with nonce {}",
+        web3_tx_dao.id,
+        user,
+        nonce
+    );
+
+    web3_tx_dao.call_data = Some(hex::encode(
+        encode_execute_meta_transaction(
+            user,
+            function_signature,
+            signature.r,
+            signature.s,
+            signature.v as u8,
+        )
+        .map_err(err_from!())?,
+    ));
+    Ok(())
+}
+
+/// Fill signature of the owner into permit call data.
+pub async fn sign_permit(
+    web3: &Web3<Http>,
+    web3_tx_dao: &mut TxDao,
+    signer: &impl Signer,
+) -> Result<(), PaymentError> {
+    let call_data = decode_data_to_bytes(web3_tx_dao)?
+        .ok_or_else(|| err_custom_create!("Missing call data for permit"))?;
+    let (owner, spender, value, deadline, sig_v) =
+        decode_erc20_permit(&call_data.0).map_err(err_from!())?;
+    if sig_v != 0 {
+        log::debug!("Permit {} already signed", web3_tx_dao.id);
+        return Ok(());
+    }
+    signer.check_if_sign_possible(owner).await.map_err(|err| {
+        err_create!(TransactionFailedError::new(&format!(
+            "Sign of permit won't be possible for given address: {owner:#x}, error: {err:?}"
+        )))
+    })?;
+
+    let token = Address::from_str(&web3_tx_dao.to_addr).map_err(err_from!())?;
+    let nonce = get_permit_nonce(web3, token, owner).await?;
+    let domain_separator = get_permit_domain_separator(web3, token).await?;
+    let hash = typed_data_hash(
+        domain_separator,
+        permit_struct_hash(owner, spender, value, nonce, deadline),
+    );
+    let signature = signer
+        .sign_hash(owner, hash)
+        .await
+        .map_err(|err| err_custom_create!("Signing permit failed due to unknown error: {err:?}"))?;
+    log::debug!(
+        "Permit {} signed by {:#x} with nonce {}",
+        web3_tx_dao.id,
+        owner,
+        nonce
+    );
+
+    web3_tx_dao.call_data = Some(hex::encode(
+        encode_erc20_permit(
+            owner,
+            spender,
+            value,
+            deadline,
+            signature.v as u8,
+            signature.r,
+            signature.s,
+        )
+        .map_err(err_from!())?,
+    ));
+    Ok(())
+}
+
 pub async fn send_transaction(
     web3: &Web3<Http>,
     web3_tx_dao: &mut TxDao,
@@ -584,6 +864,7 @@ pub async fn find_receipt_extended(
     }
 
     chain_tx_dao.to_addr = format!("{receipt_to:#x}");
+    chain_tx_dao.method = get_method_from_input(&tx.input.0).unwrap_or_default();
 
     chain_tx_dao.chain_status = receipt
         .status
@@ -605,7 +886,21 @@ pub async fn find_receipt_extended(
             .unwrap();
     let mut transfers = Vec::<ChainTransferDao>::new();
 
-    if tx.value != U256::zero() {
+    if chain_tx_dao.method == "DISPERSE.disperseEther" {
+        //value is passed through disperse contract to the receivers
+        let (recipients, values) = decode_disperse_ether(&tx.input.0).map_err(err_from!())?;
+        for (recipient, value) in recipients.into_iter().zip(values) {
+            transfers.push(ChainTransferDao {
+                id: 0,
+                from_addr: format!("{tx_from:#x}"),
+                receiver_addr: format!("{recipient:#x}"),
+                chain_id,
+                token_addr: None,
+                token_amount: value.to_string(),
+                chain_tx_id: 0,
+            });
+        }
+    } else if tx.value != U256::zero() {
         transfers.push(ChainTransferDao {
             id: 0,
             from_addr: format!("{tx_from:#x}"),
@@ -621,9 +916,13 @@ pub async fn find_receipt_extended(
     let mut transfered_to_contract_token = None;
     let mut transfered_to_contract_from = None;
 
+    //in direct mode tokens go straight from sender to receivers without passing the contract
+    let direct_mode = chain_tx_dao.method.starts_with("MULTI.golemTransferDirect");
+
     //check if there is special transfer to contract
     for log in &receipt.logs {
-        if log.topics.len() == 3 && log.topics[0] == erc20_transfer_event_signature {
+        if !direct_mode && log.topics.len() == 3 && log.topics[0] == erc20_transfer_event_signature
+        {
             let from = Address::from_slice(&log.topics[1][12..]);
             let to = Address::from_slice(&log.topics[2][12..]);
             let amount = U256::from(log.data.0.as_slice());
@@ -658,11 +957,11 @@ pub async fn find_receipt_extended(
             let from = Address::from_slice(&log.topics[1][12..]);
             let to = Address::from_slice(&log.topics[2][12..]);
             let amount = U256::from(log.data.0.as_slice());
-            if to == tx_to {
+            if to == tx_to && !direct_mode {
                 continue;
             }
 
-            if from == tx_to {
+            if from == tx_to && !direct_mode {
                 if Some(log.address) != transfered_to_contract_token {
                     return Err(err_custom_create!(
                         "Transfer from contract different token {:#x} != {:#x}",
@@ -1,27 +1,103 @@
 mod options;
-use crate::options::{CliOptions, PaymentCommands, PaymentOptions};
+use crate::options::{
+    ApprovalCommands, CliOptions, PaymentCommands, PaymentOptions, QueueCommands,
+    RecurringCommands, ScreeningCommands,
+};
 use actix_web::Scope;
 use actix_web::{web, App, HttpServer};
+use chrono::{DateTime, Utc};
 use csv::ReaderBuilder;
+use erc20_payment_lib::balance::{format_account_balances, get_account_balances};
 use erc20_payment_lib::config::AdditionalOptions;
 use erc20_payment_lib::db::create_sqlite_connection;
-use erc20_payment_lib::db::ops::insert_token_transfer;
+use erc20_payment_lib::db::model::ScreeningAddressDao;
+use erc20_payment_lib::db::ops::*;
+use erc20_payment_lib::eth::get_token_decimals;
+use erc20_payment_lib::export::{export_transfers, ExportFormat};
 use erc20_payment_lib::misc::load_public_addresses;
+use erc20_payment_lib::screening::ReceiverScreening;
+use erc20_payment_lib::sender::{
+    approve_transfer, cancel_recurring_payment, create_recurring_payment, format_simulation_report,
+    get_transfers_pending_approval, mark_tx_failed, mark_tx_replaced, record_operator_action,
+    reject_transfer, requeue_failed_transfers, retry_transfer, revoke_allowance,
+    simulate_transactions,
+};
 use erc20_payment_lib::server::*;
+use erc20_payment_lib::setup::PaymentSetup;
 use erc20_payment_lib::transaction::create_token_transfer;
+use erc20_payment_lib::utils::rust_dec_to_u256;
 use erc20_payment_lib::{
     config, err_custom_create, err_from,
     error::{CustomError, ErrorBag, PaymentError},
     misc::{display_private_keys, load_private_keys},
     runtime::start_payment_engine,
 };
+use secp256k1::SecretKey;
 use sqlx_core::sqlite::SqlitePool;
 use std::env;
+use std::str::FromStr;
 use std::sync::Arc;
 use structopt::StructOpt;
 use tokio::sync::Mutex;
 use web3::types::{Address, U256};
 
+fn print_json<T: serde::Serialize>(value: &T) -> Result<(), PaymentError> {
+    println!(
+        "{}",
+        serde_json::to_string_pretty(value)
+            .map_err(|err| err_custom_create!("Failed to serialize output: {}", err))?
+    );
+    Ok(())
+}
+
+fn get_db_filename() -> String {
+    env::var("DB_SQLITE_FILENAME").expect("Specify DB_SQLITE_FILENAME env variable")
+}
+
+async fn open_db() -> Result<SqlitePool, PaymentError> {
+    let db_filename = get_db_filename();
+    log::info!("connecting to sqlite file db: {}", db_filename);
+    create_sqlite_connection(Some(&db_filename), true).await
+}
+
+/// Setup for commands working on db or reading chain state, engine is not started with it
+fn offline_payment_setup(
+    config: &config::Config,
+    private_keys: Vec<SecretKey>,
+    receiver_accounts: Vec<Address>,
+) -> Result<PaymentSetup, PaymentError> {
+    PaymentSetup::new(
+        config,
+        private_keys,
+        receiver_accounts,
+        true,
+        false,
+        false,
+        1,
+        1,
+        false,
+    )
+}
+
+/// Given sender has to have its key loaded, first loaded account is used by default
+fn get_sender_addr(
+    from: Option<Address>,
+    public_addrs: &[Address],
+) -> Result<Address, PaymentError> {
+    match from {
+        Some(from) => {
+            if !public_addrs.contains(&from) {
+                return Err(err_custom_create!("No private key loaded for {:#x}", from));
+            }
+            Ok(from)
+        }
+        None => public_addrs
+            .first()
+            .cloned()
+            .ok_or(err_custom_create!("No private keys loaded")),
+    }
+}
+
 async fn main_internal() -> Result<(), PaymentError> {
     if let Err(err) = dotenv::dotenv() {
         return Err(err_custom_create!("No .env file found: {}", err));
@@ -34,7 +110,7 @@ async fn main_internal() -> Result<(), PaymentError> {
     env_logger::init();
     let cli: PaymentOptions = PaymentOptions::from_args();
 
-    let (private_keys, _public_addrs) = load_private_keys(
+    let (private_keys, public_addrs) = load_private_keys(
         &env::var("ETH_PRIVATE_KEYS").expect("Specify ETH_PRIVATE_KEYS env variable"),
     )?;
     let receiver_accounts = load_public_addresses(
@@ -49,18 +125,22 @@ async fn main_internal() -> Result<(), PaymentError> {
             if run_options.http && !run_options.keep_running {
                 return Err(err_custom_create!("http mode requires keep-running option"));
             }
+            if run_options.simulate && run_options.keep_running {
+                return Err(err_custom_create!(
+                    "simulate mode cannot be used with keep-running option"
+                ));
+            }
 
             let add_opt = AdditionalOptions {
                 keep_running: run_options.keep_running,
-                generate_tx_only: run_options.generate_tx_only,
+                generate_tx_only: run_options.generate_tx_only || run_options.simulate,
                 skip_multi_contract_check: run_options.skip_multi_contract_check,
+                force_flush: run_options.force_flush,
             };
-            let db_filename =
-                env::var("DB_SQLITE_FILENAME").expect("Specify DB_SQLITE_FILENAME env variable");
-            log::info!("connecting to sqlite file db: {}", db_filename);
-            let conn = create_sqlite_connection(Some(&db_filename), true).await?;
+            let db_filename = get_db_filename();
+            let conn = open_db().await?;
 
-            let sp = start_payment_engine(
+            let mut sp = start_payment_engine(
                 &private_keys,
                 &receiver_accounts,
                 &db_filename,
@@ -69,11 +149,12 @@ async fn main_internal() -> Result<(), PaymentError> {
                 Some(add_opt),
             )
             .await?;
+            let payment_setup = sp.setup.clone();
 
             let server_data = web::Data::new(Box::new(ServerData {
                 shared_state: sp.shared_state.clone(),
-                db_connection: Arc::new(Mutex::new(conn)),
-                payment_setup: sp.setup.clone(),
+                db_connection: Arc::new(Mutex::new(conn.clone())),
+                payment_setup: payment_setup.clone(),
             }));
 
             if run_options.http {
@@ -105,9 +186,35 @@ async fn main_internal() -> Result<(), PaymentError> {
                     run_options.http_port
                 );
 
-                server.await.unwrap();
+                //server handles Ctrl-C on its own and returns after stopping
+                server.await.map_err(err_from!())?;
+                sp.shutdown().await?;
             } else {
-                sp.runtime_handle.await.unwrap();
+                tokio::select! {
+                    res = &mut sp.runtime_handle => {
+                        res.map_err(|err| err_custom_create!("Payment engine task failed: {}", err))?;
+                    }
+                    _ = tokio::signal::ctrl_c() => {
+                        log::info!("Ctrl-C received");
+                        sp.shutdown().await?;
+                    }
+                }
+            }
+
+            if run_options.simulate {
+                let report = simulate_transactions(&conn, &payment_setup).await?;
+                println!("{}", format_simulation_report(&report, &payment_setup)?);
+                std::fs::write(
+                    &run_options.simulate_report,
+                    serde_json::to_string_pretty(&report).map_err(|err| {
+                        err_custom_create!("Failed to serialize simulation report: {}", err)
+                    })?,
+                )
+                .map_err(err_from!())?;
+                log::info!(
+                    "Simulation report written to {}",
+                    run_options.simulate_report
+                );
             }
         }
         PaymentCommands::ImportPayments { import_options } => {
@@ -118,10 +225,14 @@ async fn main_internal() -> Result<(), PaymentError> {
                 .delimiter(import_options.separator as u8)
                 .from_reader(std::fs::File::open(&import_options.file).map_err(err_from!())?);
 
-            let db_filename =
-                env::var("DB_SQLITE_FILENAME").expect("Specify DB_SQLITE_FILENAME env variable");
-            log::info!("connecting to sqlite file db: {}", db_filename);
-            let conn = create_sqlite_connection(Some(&db_filename), true).await?;
+            let conn = open_db().await?;
+
+            let screening = match config.screening.as_ref() {
+                Some(settings) => {
+                    Some(ReceiverScreening::load(&conn, settings, &receiver_accounts).await?)
+                }
+                None => None,
+            };
 
             let mut token_transfer_list = vec![];
             let chain_cfg =
@@ -135,9 +246,9 @@ async fn main_internal() -> Result<(), PaymentError> {
             for (line_no, result) in rdr.records().enumerate() {
                 match result {
                     Ok(r) => {
-                        if r.len() != 4 {
+                        if r.len() < 4 || r.len() > 6 {
                             return Err(err_custom_create!(
-                                "Invalid CSV format, expected 4 elements, line {}",
+                                "Invalid CSV format, expected 4 to 6 elements, line {}",
                                 line_no
                             ));
                         }
@@ -155,7 +266,25 @@ async fn main_internal() -> Result<(), PaymentError> {
                             .token
                             .clone()
                             .ok_or(err_custom_create!("Default token not found in config file"))?;
-                        let token_transfer = create_token_transfer(
+                        //optional 5th and 6th column: execute after and deadline dates (RFC 3339)
+                        let parse_date = |idx: usize, name: &str| match r.get(idx) {
+                            Some(date) if !date.trim().is_empty() => {
+                                DateTime::parse_from_rfc3339(date.trim())
+                                    .map(|date| Some(date.with_timezone(&Utc)))
+                                    .map_err(|_err| {
+                                        err_custom_create!(
+                                            "Cannot parse {}, line {}",
+                                            name,
+                                            line_no
+                                        )
+                                    })
+                            }
+                            _ => Ok(None),
+                        };
+                        let execute_after = parse_date(4, "execute after")?;
+                        let deadline = parse_date(5, "deadline")?;
+
+                        let mut token_transfer = create_token_transfer(
                             sender,
                             receiver,
                             chain_cfg.chain_id,
@@ -163,6 +292,15 @@ async fn main_internal() -> Result<(), PaymentError> {
                             Some(token.address),
                             amount,
                         );
+                        token_transfer.execute_after = execute_after;
+                        token_transfer.deadline = deadline;
+                        //rejected transfers are stored with error, so they are never processed
+                        if let Some(screening) = screening.as_ref() {
+                            if let Err(reason) = screening.check(receiver) {
+                                log::warn!("Line {}: {}", line_no, reason);
+                                token_transfer.error = Some(reason);
+                            }
+                        }
 
                         token_transfer_list.push(token_transfer);
                     }
@@ -191,6 +329,529 @@ async fn main_internal() -> Result<(), PaymentError> {
             .unwrap();
             println!("Private key: {}", hex::encode(pkey));
         }
+        PaymentCommands::RevokeAllowance { revoke_options } => {
+            let chain_cfg =
+                config
+                    .chain
+                    .get(&revoke_options.chain_name)
+                    .ok_or(err_custom_create!(
+                        "Chain {} not found in config file",
+                        revoke_options.chain_name
+                    ))?;
+            let owner = match revoke_options.owner {
+                Some(owner) => owner,
+                None => *public_addrs
+                    .first()
+                    .ok_or(err_custom_create!("No private keys loaded"))?,
+            };
+
+            let conn = open_db().await?;
+
+            let payment_setup = offline_payment_setup(&config, private_keys, receiver_accounts)?;
+            revoke_allowance(
+                &conn,
+                &payment_setup,
+                owner,
+                chain_cfg.chain_id,
+                revoke_options.token_addr,
+                revoke_options.spender,
+            )
+            .await?;
+            log::info!("Allowance revoke transaction created, run payment engine to send it");
+        }
+        PaymentCommands::Recurring { recurring_command } => {
+            let conn = open_db().await?;
+
+            match recurring_command {
+                RecurringCommands::Add { add_options } => {
+                    let chain_cfg =
+                        config
+                            .chain
+                            .get(&add_options.chain_name)
+                            .ok_or(err_custom_create!(
+                                "Chain {} not found in config file",
+                                add_options.chain_name
+                            ))?;
+                    let from = get_sender_addr(add_options.from, &public_addrs)?;
+                    let token_addr = if add_options.plain_eth {
+                        None
+                    } else {
+                        Some(
+                            add_options
+                                .token_addr
+                                .or(chain_cfg.token.as_ref().map(|t| t.address))
+                                .ok_or(err_custom_create!(
+                                    "Default token not found in config file"
+                                ))?,
+                        )
+                    };
+                    let amount = U256::from_dec_str(&add_options.amount)
+                        .map_err(|_err| err_custom_create!("Cannot parse amount"))?;
+                    let recurring_payment = create_recurring_payment(
+                        from,
+                        add_options.receiver,
+                        chain_cfg.chain_id,
+                        token_addr,
+                        amount,
+                        add_options.schedule.as_deref(),
+                        add_options.interval,
+                        add_options.start_date,
+                        add_options.end_date,
+                    )?;
+                    let recurring_payment = insert_recurring_payment(&conn, &recurring_payment)
+                        .await
+                        .map_err(err_from!())?;
+                    log::info!("Added recurring payment {}", recurring_payment.id);
+                }
+                RecurringCommands::List => {
+                    let recurring_payments = get_all_recurring_payments(&conn)
+                        .await
+                        .map_err(err_from!())?;
+                    println!(
+                        "{}",
+                        serde_json::to_string_pretty(&recurring_payments).map_err(|err| {
+                            err_custom_create!("Failed to serialize recurring payments: {}", err)
+                        })?
+                    );
+                }
+                RecurringCommands::Cancel { id } => {
+                    cancel_recurring_payment(&conn, id).await?;
+                    log::info!("Recurring payment {} cancelled", id);
+                }
+            }
+        }
+        PaymentCommands::Approval { approval_command } => {
+            let conn = open_db().await?;
+
+            let payment_setup = offline_payment_setup(&config, private_keys, receiver_accounts)?;
+            match approval_command {
+                ApprovalCommands::List => {
+                    let pending_approval =
+                        get_transfers_pending_approval(&conn, &payment_setup).await?;
+                    println!(
+                        "{}",
+                        serde_json::to_string_pretty(&pending_approval).map_err(|err| {
+                            err_custom_create!("Failed to serialize transfers: {}", err)
+                        })?
+                    );
+                }
+                ApprovalCommands::Approve { decision_options } => {
+                    let token_transfer = approve_transfer(
+                        &conn,
+                        &payment_setup,
+                        decision_options.id,
+                        &decision_options.approver,
+                        &decision_options.approver_token,
+                        decision_options.reason.as_deref(),
+                    )
+                    .await?;
+                    log::info!(
+                        "Transfer {} approval state: {}",
+                        token_transfer.id,
+                        token_transfer.approval_state.unwrap_or_default()
+                    );
+                }
+                ApprovalCommands::Reject { decision_options } => {
+                    reject_transfer(
+                        &conn,
+                        &payment_setup,
+                        decision_options.id,
+                        &decision_options.approver,
+                        &decision_options.approver_token,
+                        decision_options.reason.as_deref(),
+                    )
+                    .await?;
+                    log::info!("Transfer {} rejected", decision_options.id);
+                }
+            }
+        }
+        PaymentCommands::Transfer { transfer_options } => {
+            let chain_cfg =
+                config
+                    .chain
+                    .get(&transfer_options.chain_name)
+                    .ok_or(err_custom_create!(
+                        "Chain {} not found in config file",
+                        transfer_options.chain_name
+                    ))?;
+            if transfer_options.receivers.len() != transfer_options.amounts.len() {
+                return Err(err_custom_create!(
+                    "Number of receivers ({}) does not match number of amounts ({})",
+                    transfer_options.receivers.len(),
+                    transfer_options.amounts.len()
+                ));
+            }
+            let from = get_sender_addr(transfer_options.from, &public_addrs)?;
+            let token_addr = if transfer_options.plain_eth {
+                None
+            } else if let Some(token_addr) = transfer_options.token_addr {
+                Some(token_addr)
+            } else {
+                let token = chain_cfg
+                    .token
+                    .as_ref()
+                    .ok_or(err_custom_create!("Default token not found in config file"))?;
+                if let Some(symbol) = transfer_options.token.as_ref() {
+                    if !symbol.eq_ignore_ascii_case(&token.symbol) {
+                        return Err(err_custom_create!(
+                            "Token {} not found on chain {}",
+                            symbol,
+                            transfer_options.chain_name
+                        ));
+                    }
+                }
+                Some(token.address)
+            };
+            //decimals of token not found in config are read from the token contract
+            let decimals = match token_addr {
+                Some(token_addr)
+                    if !transfer_options.wei
+                        && chain_cfg.token.as_ref().map(|token| token.address)
+                            != Some(token_addr) =>
+                {
+                    let payment_setup = offline_payment_setup(
+                        &config,
+                        private_keys.clone(),
+                        receiver_accounts.clone(),
+                    )?;
+                    get_token_decimals(payment_setup.get_provider(chain_cfg.chain_id)?, token_addr)
+                        .await?
+                }
+                Some(_) => chain_cfg
+                    .token
+                    .as_ref()
+                    .and_then(|token| token.decimals)
+                    .unwrap_or(18),
+                None => 18,
+            };
+            let mut amounts = Vec::with_capacity(transfer_options.amounts.len());
+            for amount in &transfer_options.amounts {
+                amounts.push(if transfer_options.wei {
+                    U256::from_dec_str(amount)
+                        .map_err(|_err| err_custom_create!("Cannot parse amount {}", amount))?
+                } else {
+                    let amount = rust_decimal::Decimal::from_str(amount)
+                        .map_err(|_err| err_custom_create!("Cannot parse amount {}", amount))?;
+                    rust_dec_to_u256(amount, Some(decimals)).map_err(err_from!())?
+                });
+            }
+
+            let db_filename = get_db_filename();
+            let conn = open_db().await?;
+
+            let screening = match config.screening.as_ref() {
+                Some(settings) => {
+                    Some(ReceiverScreening::load(&conn, settings, &receiver_accounts).await?)
+                }
+                None => None,
+            };
+            //all receivers are screened first, so none of the transfers is added when any is rejected
+            if let Some(screening) = screening.as_ref() {
+                for receiver in &transfer_options.receivers {
+                    screening
+                        .check(*receiver)
+                        .map_err(|reason| err_custom_create!("{}", reason))?;
+                }
+            }
+            let mut transfer_ids = Vec::with_capacity(amounts.len());
+            let mut db_transaction = conn.begin().await.map_err(err_from!())?;
+            for (receiver, amount) in transfer_options.receivers.iter().zip(amounts) {
+                let token_transfer = create_token_transfer(
+                    from,
+                    *receiver,
+                    chain_cfg.chain_id,
+                    None,
+                    token_addr,
+                    amount,
+                );
+                let token_transfer = insert_token_transfer(&mut db_transaction, &token_transfer)
+                    .await
+                    .map_err(err_from!())?;
+                log::info!(
+                    "Added transfer {} of {} to {:#x}",
+                    token_transfer.id,
+                    amount,
+                    receiver
+                );
+                transfer_ids.push(token_transfer.id);
+            }
+            db_transaction.commit().await.map_err(err_from!())?;
+
+            if transfer_options.wait {
+                let sp = start_payment_engine(
+                    &private_keys,
+                    &receiver_accounts,
+                    &db_filename,
+                    config,
+                    Some(conn.clone()),
+                    None,
+                )
+                .await?;
+                loop {
+                    tokio::select! {
+                        _ = tokio::time::sleep(std::time::Duration::from_secs(5)) => {}
+                        _ = tokio::signal::ctrl_c() => {
+                            sp.shutdown().await?;
+                            return Err(err_custom_create!(
+                                "Interrupted, transfers are kept in queue and sent on next run"
+                            ));
+                        }
+                    }
+                    let mut finished = Vec::with_capacity(transfer_ids.len());
+                    for id in &transfer_ids {
+                        let token_transfer = get_token_transfer(&conn, *id)
+                            .await
+                            .map_err(err_from!())?
+                            .ok_or(err_custom_create!("Transfer {} not found", id))?;
+                        if token_transfer.fee_paid.is_some() || token_transfer.error.is_some() {
+                            finished.push(token_transfer);
+                        }
+                    }
+                    if finished.len() == transfer_ids.len() {
+                        sp.shutdown().await?;
+                        let failed = finished.iter().filter(|t| t.error.is_some()).count();
+                        for token_transfer in finished.iter().filter(|t| t.error.is_some()) {
+                            log::error!(
+                                "Transfer {} failed: {}",
+                                token_transfer.id,
+                                token_transfer.error.as_deref().unwrap_or_default()
+                            );
+                        }
+                        if failed > 0 {
+                            return Err(err_custom_create!(
+                                "{} of {} transfers failed",
+                                failed,
+                                transfer_ids.len()
+                            ));
+                        }
+                        log::info!("All {} transfers confirmed", transfer_ids.len());
+                        break;
+                    }
+                    //engine finishes when there is nothing to send, transfers held back
+                    //(delayed, waiting for funds or approval) are not finished then
+                    if sp.runtime_handle.is_finished() {
+                        sp.shutdown().await?;
+                        return Err(err_custom_create!(
+                            "Payment engine stopped with {} of {} transfers finished, the rest is kept in queue",
+                            finished.len(),
+                            transfer_ids.len()
+                        ));
+                    }
+                    log::info!(
+                        "Waiting for transfers, {} of {} finished",
+                        finished.len(),
+                        transfer_ids.len()
+                    );
+                }
+            }
+        }
+        PaymentCommands::Balance { balance_options } => {
+            let conn = open_db().await?;
+
+            let mut payment_setup =
+                offline_payment_setup(&config, private_keys, receiver_accounts)?;
+            if let Some(chain_name) = balance_options.chain_name {
+                let chain_id = config
+                    .chain
+                    .get(&chain_name)
+                    .ok_or(err_custom_create!(
+                        "Chain {} not found in config file",
+                        chain_name
+                    ))?
+                    .chain_id;
+                payment_setup.chain_setup.retain(|id, _| *id == chain_id);
+            }
+            let balances = get_account_balances(&conn, &payment_setup).await?;
+            if balance_options.json {
+                print_json(&balances)?;
+            } else {
+                print!("{}", format_account_balances(&balances)?);
+            }
+        }
+        PaymentCommands::Export { export_options } => {
+            let conn = open_db().await?;
+
+            let payment_setup = offline_payment_setup(&config, private_keys, receiver_accounts)?;
+            let format = ExportFormat::from_str(&export_options.format)?;
+            let filter = TransferExportFilter {
+                since: export_options.since,
+                until: export_options.until,
+                from_addr: export_options.sender.map(|sender| format!("{sender:#x}")),
+                token_addr: export_options
+                    .token_addr
+                    .map(|token_addr| format!("{token_addr:#x}")),
+                native_only: export_options.plain_eth,
+                payment_id: export_options.payment_id,
+            };
+            let content = export_transfers(&conn, &payment_setup, &filter, format).await?;
+            match export_options.output {
+                Some(output) => {
+                    std::fs::write(&output, content).map_err(err_from!())?;
+                    log::info!("Transfers exported to {}", output);
+                }
+                None => print!("{content}"),
+            }
+        }
+        PaymentCommands::Queue { queue_command } => {
+            let conn = open_db().await?;
+
+            match queue_command {
+                QueueCommands::Txs { filter, limit } => {
+                    let filter = match filter.as_str() {
+                        "all" => TRANSACTION_FILTER_ALL,
+                        "queued" => TRANSACTION_FILTER_QUEUED,
+                        "processing" => TRANSACTION_FILTER_PROCESSING,
+                        "to-process" => TRANSACTION_FILTER_TO_PROCESS,
+                        "held" => TRANSACTION_FILTER_HELD,
+                        "done" => TRANSACTION_FILTER_DONE,
+                        _ => return Err(err_custom_create!("Unknown filter {}", filter)),
+                    };
+                    let txs = get_transactions(&conn, Some(filter), limit, None)
+                        .await
+                        .map_err(err_from!())?;
+                    print_json(&txs)?;
+                }
+                QueueCommands::Tx { id } => {
+                    let tx = get_transaction(&conn, id).await.map_err(err_from!())?;
+                    let transfers = get_token_transfers_by_tx(&conn, id)
+                        .await
+                        .map_err(err_from!())?;
+                    print_json(&serde_json::json!({
+                        "tx": tx,
+                        "callData": tx.call_data,
+                        "transfers": transfers,
+                    }))?;
+                }
+                QueueCommands::Transfers { filter, limit } => {
+                    let filter = match filter.as_str() {
+                        "all" => TRANSFER_FILTER_ALL,
+                        "queued" => TRANSFER_FILTER_QUEUED,
+                        "processing" => TRANSFER_FILTER_PROCESSING,
+                        "done" => TRANSFER_FILTER_DONE,
+                        "failed" => TRANSFER_FILTER_FAILED,
+                        "dead-letter" => TRANSFER_FILTER_DEAD_LETTER,
+                        "waiting-for-funds" => TRANSFER_FILTER_WAITING_FOR_FUNDS,
+                        "deadline-exceeded" => TRANSFER_FILTER_DEADLINE_EXCEEDED,
+                        _ => return Err(err_custom_create!("Unknown filter {}", filter)),
+                    };
+                    let transfers = get_token_transfers(&conn, Some(filter), limit)
+                        .await
+                        .map_err(err_from!())?;
+                    print_json(&transfers)?;
+                }
+                QueueCommands::Transfer { id } => {
+                    let transfer = get_token_transfer(&conn, id)
+                        .await
+                        .map_err(err_from!())?
+                        .ok_or(err_custom_create!("Transfer {} not found", id))?;
+                    let tx = match transfer.tx_id {
+                        Some(tx_id) => {
+                            Some(get_transaction(&conn, tx_id).await.map_err(err_from!())?)
+                        }
+                        None => None,
+                    };
+                    print_json(&serde_json::json!({
+                        "transfer": transfer,
+                        "tx": tx,
+                    }))?;
+                }
+                QueueCommands::Requeue {
+                    id,
+                    force,
+                    actor,
+                    reason,
+                } => {
+                    let requeued =
+                        requeue_failed_transfers(&conn, id, force, &actor, &reason).await?;
+                    log::info!("Requeued {} transfers", requeued.len());
+                }
+                QueueCommands::Retry { id } => {
+                    let retry = retry_transfer(&conn, id).await?;
+                    print_json(&retry)?;
+                }
+                QueueCommands::MarkFailed {
+                    id,
+                    actor,
+                    reason,
+                    force,
+                } => {
+                    mark_tx_failed(&conn, id, &actor, &reason, force).await?;
+                    log::info!("Transaction {} marked as failed", id);
+                }
+                QueueCommands::MarkReplaced {
+                    id,
+                    replaced_by,
+                    actor,
+                    reason,
+                } => {
+                    mark_tx_replaced(&conn, id, replaced_by.as_deref(), &actor, &reason).await?;
+                    log::info!("Transaction {} marked as replaced", id);
+                }
+                QueueCommands::Action {
+                    id,
+                    action,
+                    actor,
+                    reason,
+                } => {
+                    let operator_action =
+                        record_operator_action(&conn, id, &action, &actor, &reason).await?;
+                    print_json(&operator_action)?;
+                }
+                QueueCommands::Actions { id, limit } => {
+                    let operator_actions = get_operator_actions(&conn, id, limit)
+                        .await
+                        .map_err(err_from!())?;
+                    print_json(&operator_actions)?;
+                }
+                QueueCommands::Nonces => {
+                    let nonces = get_account_nonces(&conn).await.map_err(err_from!())?;
+                    print_json(&nonces)?;
+                }
+            }
+        }
+        PaymentCommands::Screening { screening_command } => {
+            let conn = open_db().await?;
+
+            match screening_command {
+                ScreeningCommands::Add { address, note } => {
+                    insert_screening_address(
+                        &conn,
+                        &ScreeningAddressDao {
+                            id: 0,
+                            address: format!("{address:#x}"),
+                            note,
+                            created_date: Utc::now(),
+                        },
+                    )
+                    .await
+                    .map_err(err_from!())?;
+                    log::info!("Added {:#x} to screening list", address);
+                }
+                ScreeningCommands::Remove { address } => {
+                    let removed = delete_screening_address(&conn, &format!("{address:#x}"))
+                        .await
+                        .map_err(err_from!())?;
+                    if removed == 0 {
+                        return Err(err_custom_create!(
+                            "Address {:#x} not found in screening list",
+                            address
+                        ));
+                    }
+                    log::info!("Removed {:#x} from screening list", address);
+                }
+                ScreeningCommands::List => {
+                    let screening_addresses = get_all_screening_addresses(&conn)
+                        .await
+                        .map_err(err_from!())?;
+                    println!(
+                        "{}",
+                        serde_json::to_string_pretty(&screening_addresses).map_err(|err| {
+                            err_custom_create!("Failed to serialize screening list: {}", err)
+                        })?
+                    );
+                }
+            }
+        }
     }
 
     Ok(())
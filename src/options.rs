@@ -1,6 +1,8 @@
 use std::fmt::Debug;
 
+use chrono::{DateTime, Utc};
 use structopt::StructOpt;
+use web3::types::Address;
 
 #[derive(StructOpt)]
 #[structopt(about = "Payment admin tool - run options")]
@@ -17,12 +19,31 @@ pub struct RunOptions {
     )]
     pub generate_tx_only: bool,
 
+    #[structopt(
+        long = "simulate",
+        help = "Generate stubs, then check them against current chain state without sending (implies generate-tx-only)"
+    )]
+    pub simulate: bool,
+
+    #[structopt(
+        long = "simulate-report",
+        help = "File to write JSON simulation report to",
+        default_value = "simulation-report.json"
+    )]
+    pub simulate_report: String,
+
     #[structopt(
         long = "skip-multi-contract-check",
         help = "Skip multi contract check when generating txs"
     )]
     pub skip_multi_contract_check: bool,
 
+    #[structopt(
+        long = "force-flush",
+        help = "Send all pending transfers, ignoring min payout and batching policy"
+    )]
+    pub force_flush: bool,
+
     #[structopt(
         long = "service-sleep",
         help = "Sleep time between service loops in seconds",
@@ -98,6 +119,318 @@ pub struct DecryptKeyStoreOptions {
     pub password: Option<String>,
 }
 
+#[derive(StructOpt)]
+#[structopt(about = "Revoke allowance")]
+pub struct RevokeAllowanceOptions {
+    //default is Mumbai for safety
+    #[structopt(long = "chain-name", default_value = "mumbai")]
+    pub chain_name: String,
+
+    #[structopt(
+        long = "owner",
+        help = "Owner of the allowance, defaults to first loaded account"
+    )]
+    pub owner: Option<Address>,
+
+    #[structopt(long = "token-addr", help = "Token address, defaults to chain token")]
+    pub token_addr: Option<Address>,
+
+    #[structopt(
+        long = "spender",
+        help = "Spender address, defaults to chain multi contract"
+    )]
+    pub spender: Option<Address>,
+}
+
+#[derive(StructOpt)]
+#[structopt(about = "Add recurring payment")]
+pub struct RecurringAddOptions {
+    //default is Mumbai for safety
+    #[structopt(long = "chain-name", default_value = "mumbai")]
+    pub chain_name: String,
+
+    #[structopt(long = "from", help = "Sender, defaults to first loaded account")]
+    pub from: Option<Address>,
+
+    #[structopt(long = "receiver")]
+    pub receiver: Address,
+
+    #[structopt(long = "amount", help = "Amount in wei")]
+    pub amount: String,
+
+    #[structopt(long = "token-addr", help = "Token address, defaults to chain token")]
+    pub token_addr: Option<Address>,
+
+    #[structopt(long = "plain-eth", help = "Pay in native currency instead of token")]
+    pub plain_eth: bool,
+
+    #[structopt(
+        long = "schedule",
+        help = "Cron expression with seconds field, i.e. \"0 0 9 1 * *\""
+    )]
+    pub schedule: Option<String>,
+
+    #[structopt(long = "interval", help = "Interval between payments in seconds")]
+    pub interval: Option<u64>,
+
+    #[structopt(
+        long = "start-date",
+        help = "First payment date (RFC 3339), defaults to now"
+    )]
+    pub start_date: Option<DateTime<Utc>>,
+
+    #[structopt(long = "end-date", help = "No payments after this date (RFC 3339)")]
+    pub end_date: Option<DateTime<Utc>>,
+}
+
+#[derive(StructOpt)]
+#[structopt(about = "Manage recurring payments")]
+pub enum RecurringCommands {
+    Add {
+        #[structopt(flatten)]
+        add_options: RecurringAddOptions,
+    },
+    List,
+    Cancel {
+        #[structopt(long = "id")]
+        id: i64,
+    },
+}
+
+#[derive(StructOpt)]
+pub struct ApprovalDecisionOptions {
+    #[structopt(long = "id", help = "Transfer id")]
+    pub id: i64,
+
+    #[structopt(
+        long = "approver",
+        help = "Identity of the operator making the decision"
+    )]
+    pub approver: String,
+
+    #[structopt(
+        long = "approver-token",
+        env = "APPROVER_TOKEN",
+        hide_env_values = true,
+        help = "Token of the approver, has to match APPROVER_TOKEN_<NAME> set for the service"
+    )]
+    pub approver_token: String,
+
+    #[structopt(long = "reason")]
+    pub reason: Option<String>,
+}
+
+#[derive(StructOpt)]
+pub enum ApprovalCommands {
+    List,
+    Approve {
+        #[structopt(flatten)]
+        decision_options: ApprovalDecisionOptions,
+    },
+    Reject {
+        #[structopt(flatten)]
+        decision_options: ApprovalDecisionOptions,
+    },
+}
+
+#[derive(StructOpt)]
+#[structopt(about = "Send transfers to given receivers")]
+pub struct TransferOptions {
+    //default is Mumbai for safety
+    #[structopt(long = "chain-name", default_value = "mumbai")]
+    pub chain_name: String,
+
+    #[structopt(long = "from", help = "Sender, defaults to first loaded account")]
+    pub from: Option<Address>,
+
+    #[structopt(long = "receivers", use_delimiter = true, required = true)]
+    pub receivers: Vec<Address>,
+
+    #[structopt(
+        long = "amounts",
+        use_delimiter = true,
+        required = true,
+        help = "Amounts in ether/token units, in wei with --wei"
+    )]
+    pub amounts: Vec<String>,
+
+    #[structopt(long = "wei", help = "Amounts are given in wei")]
+    pub wei: bool,
+
+    #[structopt(long = "plain-eth", help = "Pay in native currency instead of token")]
+    pub plain_eth: bool,
+
+    #[structopt(
+        long = "token-addr",
+        help = "Token address, defaults to chain token, decimals of other tokens are read from the token contract"
+    )]
+    pub token_addr: Option<Address>,
+
+    #[structopt(long = "token", help = "Token symbol, has to match chain token")]
+    pub token: Option<String>,
+
+    #[structopt(long = "wait", help = "Run engine until the transfers are confirmed")]
+    pub wait: bool,
+}
+
+#[derive(StructOpt)]
+#[structopt(about = "Export completed transfers")]
+pub struct ExportOptions {
+    #[structopt(
+        long = "format",
+        help = "csv, csv-semicolon or json",
+        default_value = "csv"
+    )]
+    pub format: String,
+
+    #[structopt(long = "output", help = "Output file, stdout when not given")]
+    pub output: Option<String>,
+
+    #[structopt(long = "since", help = "Confirmed at or after date (RFC 3339)")]
+    pub since: Option<DateTime<Utc>>,
+
+    #[structopt(long = "until", help = "Confirmed before date (RFC 3339)")]
+    pub until: Option<DateTime<Utc>>,
+
+    #[structopt(long = "sender")]
+    pub sender: Option<Address>,
+
+    #[structopt(long = "token-addr")]
+    pub token_addr: Option<Address>,
+
+    #[structopt(long = "plain-eth", help = "Only native currency transfers")]
+    pub plain_eth: bool,
+
+    #[structopt(long = "payment-id", help = "Payment id, % matches any characters")]
+    pub payment_id: Option<String>,
+}
+
+#[derive(StructOpt)]
+#[structopt(about = "Show sender balances and outgoing amounts")]
+pub struct BalanceOptions {
+    #[structopt(long = "chain-name", help = "All configured chains when not given")]
+    pub chain_name: Option<String>,
+
+    #[structopt(long = "json", help = "Print JSON instead of table")]
+    pub json: bool,
+}
+
+#[derive(StructOpt)]
+pub enum QueueCommands {
+    /// List transactions
+    Txs {
+        #[structopt(
+            long = "filter",
+            help = "all, queued, processing, to-process, held or done",
+            default_value = "to-process"
+        )]
+        filter: String,
+        #[structopt(long = "limit")]
+        limit: Option<i64>,
+    },
+    /// Show transaction with its transfers
+    Tx {
+        #[structopt(long = "id")]
+        id: i64,
+    },
+    /// List transfers
+    Transfers {
+        #[structopt(
+            long = "filter",
+            help = "all, queued, waiting-for-funds, processing, done, failed, dead-letter or deadline-exceeded",
+            default_value = "queued"
+        )]
+        filter: String,
+        #[structopt(long = "limit")]
+        limit: Option<i64>,
+    },
+    /// Show transfer with its transaction
+    Transfer {
+        #[structopt(long = "id")]
+        id: i64,
+    },
+    /// Clear error and transaction of failed transfers, so they are sent again
+    Requeue {
+        #[structopt(long = "id", help = "Transfer id, all failed transfers when not given")]
+        id: Option<i64>,
+        #[structopt(
+            long = "force",
+            help = "Requeue transfers of sent transactions too, they are paid twice if the transaction gets confirmed"
+        )]
+        force: bool,
+        #[structopt(long = "actor")]
+        actor: String,
+        #[structopt(long = "reason")]
+        reason: String,
+    },
+    /// Create new attempt of failed transfer, original transfer is kept for history
+    Retry {
+        #[structopt(long = "id")]
+        id: i64,
+    },
+    /// Stop processing transaction and mark its transfers as failed
+    MarkFailed {
+        #[structopt(long = "id")]
+        id: i64,
+        #[structopt(long = "actor")]
+        actor: String,
+        #[structopt(long = "reason")]
+        reason: String,
+        #[structopt(
+            long = "force",
+            help = "Mark sent transaction too, use only when it is known to be dropped"
+        )]
+        force: bool,
+    },
+    /// Stop processing transaction replaced on chain, its transfers are returned to queue
+    MarkReplaced {
+        #[structopt(long = "id")]
+        id: i64,
+        #[structopt(long = "replaced-by", help = "Hash of the replacing transaction")]
+        replaced_by: Option<String>,
+        #[structopt(long = "actor")]
+        actor: String,
+        #[structopt(long = "reason")]
+        reason: String,
+    },
+    /// Show confirmed and pending nonces per account
+    Nonces,
+    /// Record operator action (skip, hold, release, retry or cancel) applied by the engine
+    Action {
+        #[structopt(long = "id", help = "Transaction id")]
+        id: i64,
+        #[structopt(long = "action", help = "skip, hold, release, retry or cancel")]
+        action: String,
+        #[structopt(long = "actor")]
+        actor: String,
+        #[structopt(long = "reason")]
+        reason: String,
+    },
+    /// List operator actions
+    Actions {
+        #[structopt(long = "id", help = "Transaction id, all actions when not given")]
+        id: Option<i64>,
+        #[structopt(long = "limit")]
+        limit: Option<i64>,
+    },
+}
+
+#[derive(StructOpt)]
+pub enum ScreeningCommands {
+    /// Add address to screening_address table (used when screening has database = true)
+    Add {
+        #[structopt(long = "address")]
+        address: Address,
+        #[structopt(long = "note")]
+        note: Option<String>,
+    },
+    Remove {
+        #[structopt(long = "address")]
+        address: Address,
+    },
+    List,
+}
+
 #[derive(StructOpt)]
 #[structopt(about = "Payment admin tool")]
 pub enum PaymentCommands {
@@ -113,6 +446,38 @@ pub enum PaymentCommands {
         #[structopt(flatten)]
         decrypt_options: DecryptKeyStoreOptions,
     },
+    RevokeAllowance {
+        #[structopt(flatten)]
+        revoke_options: RevokeAllowanceOptions,
+    },
+    Recurring {
+        #[structopt(subcommand)]
+        recurring_command: RecurringCommands,
+    },
+    Approval {
+        #[structopt(subcommand)]
+        approval_command: ApprovalCommands,
+    },
+    Screening {
+        #[structopt(subcommand)]
+        screening_command: ScreeningCommands,
+    },
+    Transfer {
+        #[structopt(flatten)]
+        transfer_options: TransferOptions,
+    },
+    Export {
+        #[structopt(flatten)]
+        export_options: ExportOptions,
+    },
+    Queue {
+        #[structopt(subcommand)]
+        queue_command: QueueCommands,
+    },
+    Balance {
+        #[structopt(flatten)]
+        balance_options: BalanceOptions,
+    },
 }
 
 #[derive(StructOpt)]